@@ -1,39 +1,33 @@
-use crate::grid::{BlockedTiles, GridConfig, GridPosition, SelectedPath, SelectedTile, Tile};
-use crate::pathfinding::{calculate_a_star_path, AllUnitsActed};
-use crate::player_units::Player;
+use crate::attacks::{AttackAnimation, PendingAttack};
+use crate::fog::{update_vision, TeamVision};
+use crate::grid::{GridConfig, GridPosition, LevelEntity, SelectedPath, SelectedTile};
+use crate::level::{Level, LevelHandles, UnitJson};
+use crate::pathfinding::AllUnitsActed;
+use crate::replay::{BattleCommand, Playback};
+use crate::rng::GameRng;
+use crate::sim::{Coord, Team};
 use crate::states::TurnPhase;
-use crate::units::{ActiveUnit, Attack, Health, Movement, Spawners, Team, Unit};
+use crate::units::{
+    apply_unit_stats, ActiveUnit, Attack, BattleUnits, Defense, Health, Movement, Spawners, Unit,
+    UnitSprite, Vision,
+};
 
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::collections::HashSet;
 
 pub struct AiUnitsPlugin;
 
 #[derive(Component, Debug)]
 pub struct Ai;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct WaveUnit {
-    pub count: i32,
-    pub unit: String,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct UnitJson {
-    pub sprite: String,
-    pub movement: i32,
-    pub health: i32,
-    pub damage: i32,
-    pub range: i32,
-}
-#[derive(Serialize, Deserialize, Debug)]
-struct Level {
-    pub waves: Vec<Vec<WaveUnit>>,
-}
 #[derive(Default)]
-pub struct WaveIndex(usize);
+pub struct WaveIndex(pub usize);
+
+/// Enemy definition an ai unit was spawned from.
+#[derive(Component, Debug)]
+pub struct EnemyKind(pub Handle<UnitJson>);
 
 fn setup_active(mut commands: Commands) {
     commands.insert_resource(ActiveUnit { ..default() });
@@ -46,34 +40,30 @@ fn move_active_unit(
     mut selected_path: ResMut<SelectedPath>,
     mut ai_units: Query<(Entity, &mut Transform, &mut GridPosition, &mut Unit), With<Ai>>,
     mut phase: ResMut<State<TurnPhase>>,
+    playback: Res<Playback>,
 ) {
     match active_res.value {
         Some(active) => match ai_units.get_mut(active) {
             Ok((_e, mut transform, mut grid, mut ai)) => {
                 let mut should_pop = false;
                 if let Some(next_tile) = selected_path.tiles.last() {
-                    let direction = Vec3::new(
-                        next_tile.0 as f32 * grid_config.tile_size - grid_config.offset(),
-                        next_tile.1 as f32 * grid_config.tile_size - grid_config.offset(),
-                        0.0,
-                    ) - transform.translation;
+                    let target = grid_config
+                        .grid_to_world(*next_tile)
+                        .extend(transform.translation.z);
+                    let direction = target - transform.translation;
 
                     if direction.length() > 1.0 {
                         transform.translation +=
-                            direction.normalize() * time.delta_seconds() * 100.0;
+                            direction.normalize() * time.delta_seconds() * 100.0 * playback.speed();
                     } else {
-                        transform.translation = Vec3::new(
-                            next_tile.0 as f32 * grid_config.tile_size - grid_config.offset(),
-                            next_tile.1 as f32 * grid_config.tile_size - grid_config.offset(),
-                            0.0,
-                        );
+                        transform.translation = target;
                         grid.x = next_tile.0;
                         grid.y = next_tile.1;
                         should_pop = true;
                     }
                 } else {
-                    ai.has_acted = true;
-                    phase.set(TurnPhase::AISelectUnit).unwrap();
+                    ai.has_moved = true;
+                    phase.set(TurnPhase::AISelectTarget).unwrap();
                 }
                 if should_pop {
                     selected_path.tiles.pop();
@@ -85,47 +75,53 @@ fn move_active_unit(
     }
 }
 
-fn spawn_unit(
-    x: f32,
-    y: f32,
+pub fn spawn_unit(
+    world: Vec2,
     i: i32,
     grid: (i32, i32),
     commands: &mut Commands,
     asset_server: &Res<AssetServer>,
     sprite_path: &str,
+    attack_sprite_path: &str,
     movement: i32,
     health: i32,
-    dmg: i32,
-    range: i32,
+    attack: Attack,
+    defense: Defense,
+    vision: i32,
 ) -> Entity {
     commands
         .spawn()
         .insert_bundle(SpatialBundle {
-            transform: Transform::from_translation(Vec3::new(x, y, 1.0)),
+            transform: Transform::from_translation(world.extend(1.0)),
             ..default()
         })
         .with_children(|parent| {
-            parent.spawn_bundle(SpriteBundle {
-                texture: asset_server.load(sprite_path),
-                transform: Transform::from_translation(Vec3::new(0.0, 0.0, 5.0)),
-                ..default()
-            });
+            parent
+                .spawn_bundle(SpriteBundle {
+                    texture: asset_server.load(sprite_path),
+                    transform: Transform::from_translation(Vec3::new(0.0, 0.0, 5.0)),
+                    ..default()
+                })
+                .insert(UnitSprite {
+                    idle: asset_server.load(sprite_path),
+                    attack: asset_server.load(attack_sprite_path),
+                });
         })
         .insert(Unit {
-            has_acted: false,
+            has_moved: false,
+            has_attacked: false,
             team: Team::AI,
         })
         .insert(Ai)
         .insert(Name::new(format!("Ai Unit {}", i)))
-        .insert(Attack {
-            dmg: dmg,
-            range: range,
-        })
+        .insert(attack)
+        .insert(defense)
         .insert(Movement { distance: movement })
         .insert(Health {
             max: health,
             value: health,
         })
+        .insert(Vision { radius: vision })
         .insert(GridPosition {
             x: grid.0,
             y: grid.1,
@@ -133,161 +129,227 @@ fn spawn_unit(
         .id()
 }
 
+/// Wave unit that found no free tile, it waits for the end of an ai turn to appear.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DelayedSpawn {
+    pub unit: String,
+    pub zone: Option<String>,
+}
+
+#[derive(Default)]
+pub struct DelayedSpawns(pub Vec<DelayedSpawn>);
+
+/// Everything needed to put enemies on the board.
+#[derive(SystemParam)]
+pub struct EnemySpawner<'w, 's> {
+    commands: Commands<'w, 's>,
+    asset_server: Res<'w, AssetServer>,
+    grid_config: Res<'w, GridConfig>,
+    spawns: Res<'w, Spawners>,
+    handles: Res<'w, LevelHandles>,
+    enemies: Res<'w, Assets<UnitJson>>,
+    rng: ResMut<'w, GameRng>,
+    units: Query<'w, 's, &'static GridPosition, With<Unit>>,
+}
+
+impl<'w, 's> EnemySpawner<'w, 's> {
+    /// Picks a free tile in the unit's zone, or any free ai spawn when the zone is full.
+    fn pick_tile(&mut self, zone: Option<&str>, occupied: &HashSet<Coord>) -> Option<Coord> {
+        let free = |tiles: &[Coord]| -> Vec<Coord> {
+            tiles
+                .iter()
+                .copied()
+                .filter(|tile| !occupied.contains(tile))
+                .collect()
+        };
+        let mut candidates = zone
+            .and_then(|zone| self.spawns.zones.get(zone))
+            .map_or_else(Vec::new, |tiles| free(tiles));
+        if candidates.is_empty() {
+            if let Some(zone) = zone {
+                info!("spawn zone {} is full, using any ai spawn", zone);
+            }
+            candidates = free(&self.spawns.ai_locations);
+        }
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(candidates[self.rng.gen_range(0..candidates.len())])
+    }
+
+    /// Spawns the queued units on free tiles and returns the ones that have to wait.
+    ///
+    /// At level start the squad is spawned in the same pass, so its spawn points
+    /// count as taken instead of the units on the board.
+    fn spawn(&mut self, queue: Vec<DelayedSpawn>, level_start: bool) -> Vec<DelayedSpawn> {
+        let mut occupied: HashSet<Coord> = if level_start {
+            self.spawns.player_locations.iter().copied().collect()
+        } else {
+            self.units.iter().map(|grid| (grid.x, grid.y)).collect()
+        };
+        let mut units = Vec::new();
+        let mut delayed = Vec::new();
+        for spawn in queue {
+            let (unit, kind) = match (
+                self.handles.enemy(&spawn.unit, &self.enemies),
+                self.handles.enemies.get(&spawn.unit),
+            ) {
+                (Some(unit), Some(kind)) => (unit, kind.clone()),
+                _ => {
+                    warn!("enemy {} is not loaded, skipping it", spawn.unit);
+                    continue;
+                }
+            };
+            let sprite = format!("sprites/{}", unit.sprite);
+            let attack_sprite = format!("sprites/{}", unit.attack_sprite());
+            let (movement, health, attack, defense, vision) = (
+                unit.movement,
+                unit.health,
+                Attack::from(unit),
+                Defense::from(unit),
+                unit.vision,
+            );
+            let grid = match self.pick_tile(spawn.zone.as_deref(), &occupied) {
+                Some(grid) => grid,
+                None => {
+                    info!("no free tile for {}, it will spawn later", spawn.unit);
+                    delayed.push(spawn);
+                    continue;
+                }
+            };
+            occupied.insert(grid);
+            let unit = spawn_unit(
+                self.grid_config.grid_to_world(grid),
+                units.len() as i32,
+                grid,
+                &mut self.commands,
+                &self.asset_server,
+                &sprite,
+                &attack_sprite,
+                movement,
+                health,
+                attack,
+                defense,
+                vision,
+            );
+            self.commands.entity(unit).insert(EnemyKind(kind));
+            units.push(unit);
+        }
+        if !units.is_empty() {
+            self.commands
+                .spawn()
+                .insert(Name::new("Ai Units"))
+                .insert(LevelEntity)
+                .insert_bundle(SpatialBundle::default())
+                .push_children(&units);
+        }
+        delayed
+    }
+}
+
 pub fn spawn_wave(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    grid_config: Res<GridConfig>,
+    mut spawner: EnemySpawner,
     mut wave_index: ResMut<WaveIndex>,
-    spawns: Res<Spawners>,
+    mut delayed: ResMut<DelayedSpawns>,
+    levels: Res<Assets<Level>>,
+    phase: Res<State<TurnPhase>>,
 ) {
-    let mut rng = rand::thread_rng();
-    let level_file =
-        fs::File::open("assets/data/levels/001.json").expect("file should open read only");
-    let level_json: serde_json::Value =
-        serde_json::from_reader(level_file).expect("file should be proper JSON");
-    let level: Level = serde_json::from_value(level_json).unwrap();
-
-    let mut sprites: Vec<String> = Vec::new();
-    let mut movements: Vec<i32> = Vec::new();
-    let mut healths: Vec<i32> = Vec::new();
-    let mut dmgs: Vec<i32> = Vec::new();
-    let mut ranges: Vec<i32> = Vec::new();
-    let mut positions: Vec<(f32, f32)> = Vec::new();
-    for wave_unit in &level.waves[wave_index.0] {
-        let unit_file = fs::File::open(format!(
-            "assets/data/enemies/{}.json",
-            wave_unit.unit.to_string()
-        ))
-        .expect("file should open read only");
-        let unit_json: serde_json::Value =
-            serde_json::from_reader(unit_file).expect("file should be proper JSON");
-        let unit: UnitJson = serde_json::from_value(unit_json).unwrap();
-        for _i in 0..wave_unit.count {
-            sprites.push(format!("sprites/{}", unit.sprite.to_string()));
-            movements.push(unit.movement);
-            healths.push(unit.health);
-            dmgs.push(unit.damage);
-            ranges.push(unit.range);
-            let i: usize = rng.gen_range(0..spawns.ai_locations.len());
-            positions.push(spawns.ai_locations[i]);
+    let level = levels
+        .get(&spawner.handles.level)
+        .expect("level should be loaded before LoadLevel");
+    // units still waiting from earlier waves go first
+    let mut queue: Vec<DelayedSpawn> = delayed.0.drain(..).collect();
+    if let Some(wave) = level.waves.get(wave_index.0) {
+        for wave_unit in wave {
+            for _i in 0..wave_unit.count {
+                queue.push(DelayedSpawn {
+                    unit: wave_unit.unit.clone(),
+                    zone: wave_unit.zone.clone(),
+                });
+            }
         }
+        wave_index.0 += 1;
     }
+    delayed.0 = spawner.spawn(queue, *phase.current() == TurnPhase::LoadLevel);
+}
 
-    let mut units = Vec::new();
+/// Gives waiting units another chance once the ai turn is over.
+fn spawn_delayed_units(
+    mut spawner: EnemySpawner,
+    mut delayed: ResMut<DelayedSpawns>,
+    all_acted: Res<AllUnitsActed>,
+) {
+    if all_acted.value && !delayed.0.is_empty() {
+        let queue = std::mem::take(&mut delayed.0);
+        delayed.0 = spawner.spawn(queue, false);
+    }
+}
 
-    for i in 0..sprites.len() as i32 {
-        let x = positions[i as usize].0;
-        let y = positions[i as usize].1;
-        let unit = spawn_unit(
-            x,
-            y,
-            i,
-            (
-                ((positions[i as usize].0 / grid_config.tile_size)
-                    + (grid_config.offset() / grid_config.tile_size)) as i32,
-                ((positions[i as usize].1 / grid_config.tile_size)
-                    + (grid_config.offset() / grid_config.tile_size)) as i32,
-            ),
-            &mut commands,
-            &asset_server,
-            &sprites[i as usize],
-            movements[i as usize],
-            healths[i as usize],
-            dmgs[i as usize],
-            ranges[i as usize],
-        );
-        units.push(unit);
+fn reload_enemy_stats(
+    mut events: EventReader<AssetEvent<UnitJson>>,
+    enemies: Res<Assets<UnitJson>>,
+    mut ai_units: Query<
+        (
+            &EnemyKind,
+            &mut Movement,
+            &mut Health,
+            &mut Attack,
+            &mut Defense,
+            &mut Vision,
+        ),
+        With<Ai>,
+    >,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            if let Some(unit) = enemies.get(handle) {
+                for (kind, mut movement, mut health, mut attack, mut defense, mut vision) in
+                    ai_units.iter_mut()
+                {
+                    if kind.0 == *handle {
+                        apply_unit_stats(
+                            unit,
+                            &mut movement,
+                            &mut health,
+                            &mut attack,
+                            &mut defense,
+                            &mut vision,
+                        );
+                    }
+                }
+            }
+        }
     }
-    commands
-        .spawn()
-        .insert(Name::new("Ai Units"))
-        .insert_bundle(SpatialBundle::default())
-        .push_children(&units);
-    wave_index.0 += 1;
 }
 
 fn select_move(
     active_res: Res<ActiveUnit>,
-    unit_grids: Query<(Entity, &GridPosition), Without<Tile>>,
-    movements: Query<(Entity, &Movement, &Attack, &Transform), With<Ai>>,
+    units: BattleUnits,
     mut selected_tile: ResMut<SelectedTile>,
     mut phase: ResMut<State<TurnPhase>>,
-    tiles: Query<(&mut Tile, &GridPosition, &mut Sprite), With<Tile>>,
-    player_grids_q: Query<(&GridPosition, &Transform), With<Player>>,
-    blocked: Res<BlockedTiles>,
-    grid_config: Res<GridConfig>,
+    vision: Res<TeamVision>,
+    mut playback: ResMut<Playback>,
+    mut decisions: EventWriter<BattleCommand>,
 ) {
+    if !playback.decide() {
+        return;
+    }
+    let (battle, entities) = units.battle(Team::AI);
     match active_res.value {
-        Some(active) => match unit_grids.get(active) {
-            Ok((_e, active_grid)) => match movements.get(active) {
-                Ok((_e, active_movement, active_attack, active_transform)) => {
-                    let mut reachable: Vec<(&Tile, &GridPosition, &Sprite)> = tiles
-                        .iter()
-                        .filter(|(tile, grid, _s)| {
-                            calculate_a_star_path(
-                                (active_grid.x, active_grid.y),
-                                (grid.x, grid.y),
-                                &blocked,
-                            )
-                            .len() as i32
-                                <= active_movement.distance
-                                && !tile.blocked
-                        })
-                        .collect();
-                    let mut player_grids: Vec<(&GridPosition, &Transform)> =
-                        player_grids_q.iter().collect();
-                    player_grids.sort_by(|(g_a, _t_a), (g_b, _t_b)| {
-                        calculate_a_star_path(
-                            (g_a.x, g_a.y),
-                            (active_grid.x, active_grid.y),
-                            &blocked,
-                        )
-                        .len()
-                        .cmp(
-                            &calculate_a_star_path(
-                                (g_b.x, g_b.y),
-                                (active_grid.x, active_grid.y),
-                                &blocked,
-                            )
-                            .len(),
-                        )
-                    });
-
-                    let (closest_player_grid, closest_player_transform) = player_grids[0];
-                    let dist = closest_player_transform
-                        .translation
-                        .distance(active_transform.translation);
-                    if dist <= active_attack.range as f32 * grid_config.tile_size {
-                        selected_tile.x = active_grid.x;
-                        selected_tile.y = active_grid.y;
-                    } else {
-                        reachable.sort_by(|a, b| {
-                            calculate_a_star_path(
-                                (closest_player_grid.x, closest_player_grid.y),
-                                (a.1.x, a.1.y),
-                                &blocked,
-                            )
-                            .len()
-                            .cmp(
-                                &calculate_a_star_path(
-                                    (closest_player_grid.x, closest_player_grid.y),
-                                    (b.1.x, b.1.y),
-                                    &blocked,
-                                )
-                                .len(),
-                            )
-                        });
-
-                        selected_tile.x = reachable[0].1.x;
-                        selected_tile.y = reachable[0].1.y;
-                    }
-
-                    selected_tile.set_changed();
-                    phase.set(TurnPhase::AIDoMove).unwrap();
-                }
-                Err(_) => {}
-            },
-            Err(_) => {}
+        Some(active) => match entities.iter().position(|entity| *entity == active) {
+            Some(i) => {
+                // the ai only chases what its units can see
+                let (x, y) = battle.ai_move(i, &vision.ai);
+                selected_tile.x = x;
+                selected_tile.y = y;
+                selected_tile.set_changed();
+                decisions.send(BattleCommand::Move {
+                    team: Team::AI,
+                    to: (x, y),
+                });
+                phase.set(TurnPhase::AIDoMove).unwrap();
+            }
+            None => {}
         },
         None => {}
     }
@@ -298,34 +360,24 @@ fn select_unit(
     mut active_res: ResMut<ActiveUnit>,
     mut phase: ResMut<State<TurnPhase>>,
     mut all_acted: ResMut<AllUnitsActed>,
+    mut playback: ResMut<Playback>,
+    mut decisions: EventWriter<BattleCommand>,
 ) {
     if !all_acted.value {
-        for (entity, unit, _grid) in entities.into_iter() {
-            if unit.has_acted == false {
-                active_res.value = entity.into();
-                active_res.set_changed();
-                phase.set(TurnPhase::AISelectMove).unwrap();
-                break;
+        if let Some((entity, unit, grid)) = entities.iter().find(|(_e, unit, _g)| !unit.is_done()) {
+            if !playback.decide() {
+                return;
             }
-        }
-    } else {
-        all_acted.value = false;
-    }
-}
-
-fn select_attacker(
-    entities: Query<(Entity, &Unit), With<Ai>>,
-    mut active_res: ResMut<ActiveUnit>,
-    mut phase: ResMut<State<TurnPhase>>,
-    mut all_acted: ResMut<AllUnitsActed>,
-) {
-    if !all_acted.value {
-        for (entity, unit) in entities.into_iter() {
-            if unit.has_acted == false {
-                active_res.value = entity.into();
-                active_res.set_changed();
+            decisions.send(BattleCommand::Select {
+                team: Team::AI,
+                unit: (grid.x, grid.y),
+            });
+            active_res.value = entity.into();
+            active_res.set_changed();
+            if unit.has_moved {
                 phase.set(TurnPhase::AISelectTarget).unwrap();
-                break;
+            } else {
+                phase.set(TurnPhase::AISelectMove).unwrap();
             }
         }
     } else {
@@ -333,48 +385,27 @@ fn select_attacker(
     }
 }
 
-fn check_ai_has_moved(
-    mut ai_units: Query<&mut Unit, With<Ai>>,
+fn check_ai_turn_done(
+    mut units: BattleUnits,
     mut phase: ResMut<State<TurnPhase>>,
     mut all_acted: ResMut<AllUnitsActed>,
 ) {
-    let mut still_to_act = false;
-    for unit in ai_units.iter() {
-        if !unit.has_acted {
-            still_to_act = true;
-        }
-    }
-    if !still_to_act {
-        for mut unit in ai_units.iter_mut() {
-            unit.has_acted = false;
-        }
+    let (mut battle, entities) = units.battle(Team::AI);
+    if battle.turn_over() {
+        battle.end_turn();
+        units.apply(&battle, &entities);
         all_acted.value = true;
-        phase.set(TurnPhase::AISelectAttacker).unwrap();
+        phase.set(TurnPhase::SelectUnit).unwrap();
     }
 }
 
-fn check_enemy_has_attacked(
-    mut ai_units: Query<&mut Unit, With<Ai>>,
+/// Calls the next wave once the board is clear and no unit is still waiting to spawn.
+pub fn check_remaining_units(
+    units: BattleUnits,
+    delayed: Res<DelayedSpawns>,
     mut phase: ResMut<State<TurnPhase>>,
-    mut all_acted: ResMut<AllUnitsActed>,
 ) {
-    let mut still_to_act = false;
-    for unit in ai_units.iter() {
-        if !unit.has_acted {
-            still_to_act = true;
-        }
-    }
-    if !still_to_act {
-        for mut unit in ai_units.iter_mut() {
-            unit.has_acted = false;
-        }
-        all_acted.value = true;
-        phase.set(TurnPhase::SelectUnit).unwrap();
-    }
-}
-
-fn check_remaining_units(ai_units: Query<&Ai>, mut phase: ResMut<State<TurnPhase>>) {
-    if ai_units.is_empty() {
+    if units.battle(Team::PLAYER).0.winner() == Some(Team::PLAYER) && delayed.0.is_empty() {
         match phase.set(TurnPhase::AiSpawnWave) {
             Ok(_) => {}
             Err(_) => {}
@@ -382,44 +413,73 @@ fn check_remaining_units(ai_units: Query<&Ai>, mut phase: ResMut<State<TurnPhase
     }
 }
 fn select_target(
-    mut ai_units: Query<(Entity, &mut Unit, &GridPosition, &Attack), With<Ai>>,
-    mut player_units: Query<(Entity, &GridPosition, &Transform, &mut Health), With<Player>>,
+    mut units: BattleUnits,
     active_res: Res<ActiveUnit>,
     mut phase: ResMut<State<TurnPhase>>,
-    mut commands: Commands,
+    mut playback: ResMut<Playback>,
+    mut decisions: EventWriter<BattleCommand>,
+    mut pending: ResMut<PendingAttack>,
+    vision: Res<TeamVision>,
+    mut rng: ResMut<GameRng>,
 ) {
+    if !playback.decide() {
+        return;
+    }
+    let (mut battle, entities) = units.battle(Team::AI);
     match active_res.value {
-        Some(active) => match ai_units.get_mut(active) {
-            Ok((_active, mut active_ai, active_grid, active_attack)) => {
-                let selection = player_units
-                    .iter_mut()
-                    .find(|(_e, grid, _transform, _health)| {
-                        let dist = std::cmp::max(
-                            i32::abs(grid.x - active_grid.x),
-                            i32::abs(grid.y - active_grid.y),
-                        );
-                        dist > 0 && dist <= active_attack.range
-                    });
-                match selection {
-                    Some((e, _g, _t, mut target_health)) => {
-                        target_health.value -= active_attack.dmg;
-                        if target_health.value <= 0 {
-                            commands.entity(e).despawn_recursive();
+        Some(active) => match entities.iter().position(|entity| *entity == active) {
+            Some(i) => {
+                let exchange = match battle.ai_target(i, &vision.ai) {
+                    Some(target) => match battle.attack(i, target, &mut *rng) {
+                        Ok(exchange) => Some((target, exchange)),
+                        Err(err) => {
+                            warn!("the attack is not allowed: {:?}", err);
+                            None
                         }
-                        phase.set(TurnPhase::AISelectAttacker).unwrap();
-                        active_ai.has_acted = true;
+                    },
+                    None => None,
+                };
+                match exchange {
+                    Some((target, exchange)) => {
+                        decisions.send(BattleCommand::Attack {
+                            team: Team::AI,
+                            target: battle.units[target].pos,
+                        });
+                        units.apply(&battle, &entities);
+                        pending.0 = Some(AttackAnimation::new(
+                            &battle, &entities, i, target, exchange,
+                        ));
+                        phase.set(TurnPhase::AIDoAttack).unwrap();
                     }
                     None => {
-                        phase.set(TurnPhase::AISelectAttacker).unwrap();
-                        active_ai.has_acted = true;
+                        decisions.send(BattleCommand::Wait { team: Team::AI });
+                        battle.wait(i).ok();
+                        units.apply(&battle, &entities);
+                        phase.set(TurnPhase::AISelectUnit).unwrap();
                     }
                 }
             }
-            Err(_) => {}
+            None => {}
         },
         None => {}
     }
 }
+/// Runs once the next wave had its chance to spawn, the level is won if none came.
+fn start_player_turn(
+    mut units: BattleUnits,
+    delayed: Res<DelayedSpawns>,
+    mut phase: ResMut<State<TurnPhase>>,
+) {
+    let (mut battle, entities) = units.battle(Team::AI);
+    if battle.winner() == Some(Team::PLAYER) && delayed.0.is_empty() {
+        phase.set(TurnPhase::Victory).unwrap();
+        return;
+    }
+    battle.start_turn(Team::PLAYER);
+    units.apply(&battle, &entities);
+    phase.set(TurnPhase::SelectUnit).unwrap();
+}
+
 fn clear_active_unit(mut active: ResMut<ActiveUnit>) {
     active.value = None;
 }
@@ -427,31 +487,45 @@ fn clear_active_unit(mut active: ResMut<ActiveUnit>) {
 impl Plugin for AiUnitsPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<WaveIndex>()
-            .add_startup_system(spawn_wave)
+            .init_resource::<DelayedSpawns>()
             .add_startup_system(setup_active)
+            .add_system_set(
+                SystemSet::on_enter(TurnPhase::LoadLevel)
+                    .with_system(spawn_wave.after(crate::grid::create_level)),
+            )
             .add_system_set(SystemSet::on_enter(TurnPhase::AiSpawnWave).with_system(spawn_wave))
+            .add_system(reload_enemy_stats)
+            .add_system_set(
+                SystemSet::on_update(TurnPhase::AiSpawnWave).with_system(start_player_turn),
+            )
             .add_system_set(SystemSet::on_update(TurnPhase::AIDoMove).with_system(move_active_unit))
-            .add_system_set(SystemSet::on_update(TurnPhase::AISelectMove).with_system(select_move))
+            // vision is refreshed right before deciding, a unit may have moved this same frame
             .add_system_set(
-                SystemSet::on_update(TurnPhase::AISelectUnit)
-                    .with_system(check_ai_has_moved)
-                    .with_system(select_unit.after(check_ai_has_moved)),
+                SystemSet::on_update(TurnPhase::AISelectMove)
+                    .with_system(update_vision.before(select_move))
+                    .with_system(select_move),
             )
             .add_system_set(
-                SystemSet::on_update(TurnPhase::AISelectAttacker)
-                    .with_system(check_enemy_has_attacked)
-                    .with_system(select_attacker.after(check_enemy_has_attacked)),
+                SystemSet::on_update(TurnPhase::AISelectUnit)
+                    .with_system(check_ai_turn_done)
+                    .with_system(
+                        spawn_delayed_units
+                            .after(check_ai_turn_done)
+                            .before(select_unit),
+                    )
+                    .with_system(select_unit.after(check_ai_turn_done)),
             )
             .add_system_set(
                 SystemSet::on_update(TurnPhase::AISelectTarget)
-                    .with_system(check_remaining_units)
+                    .with_system(update_vision.before(select_target))
                     .with_system(select_target),
             )
             .add_system_set(
                 SystemSet::on_enter(TurnPhase::AISelectUnit).with_system(clear_active_unit),
             )
+            // state changes are refused while entering a phase, so this waits for the update
             .add_system_set(
-                SystemSet::on_update(TurnPhase::SelectAttacker).with_system(check_remaining_units),
+                SystemSet::on_update(TurnPhase::SelectUnit).with_system(check_remaining_units),
             );
     }
 }
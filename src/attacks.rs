@@ -0,0 +1,284 @@
+use bevy::prelude::*;
+
+use crate::{
+    grid::{GridConfig, LevelEntity},
+    replay::Playback,
+    sim::{AttackResult, Battle, Exchange, Roll},
+    states::TurnPhase,
+    units::{DamageDealt, Health, UnitSprite},
+};
+
+pub struct AttacksPlugin;
+
+const STRIKE_TIME: f32 = 0.25;
+const RECOVER_TIME: f32 = 0.25;
+const DEATH_TIME: f32 = 0.5;
+const LUNGE_DISTANCE: f32 = 24.0;
+
+/// Attack decided in `SelectTarget` or `AISelectTarget`, the damage lands once its
+/// animation reaches the target. A target that survives strikes back afterwards if it can.
+pub struct AttackAnimation {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub from: (i32, i32),
+    pub to: (i32, i32),
+    pub exchange: Exchange,
+    pub melee: bool,
+    pub counter_melee: bool,
+    countering: bool,
+    elapsed: f32,
+    hit: bool,
+    killed: bool,
+    projectile: Option<Entity>,
+}
+
+struct Blow {
+    striker: Entity,
+    struck: Entity,
+    from: (i32, i32),
+    to: (i32, i32),
+    roll: Roll,
+    result: AttackResult,
+    melee: bool,
+}
+
+impl AttackAnimation {
+    pub fn new(
+        battle: &Battle,
+        entities: &[Entity],
+        attacker: usize,
+        target: usize,
+        exchange: Exchange,
+    ) -> AttackAnimation {
+        let (from, to) = (&battle.units[attacker], &battle.units[target]);
+        AttackAnimation {
+            attacker: entities[attacker],
+            target: entities[target],
+            from: from.pos,
+            to: to.pos,
+            exchange,
+            melee: from.range <= 1,
+            counter_melee: to.range <= 1,
+            countering: false,
+            elapsed: 0.0,
+            hit: false,
+            killed: false,
+            projectile: None,
+        }
+    }
+
+    fn blow(&self) -> Blow {
+        match (self.countering, self.exchange.counter) {
+            (true, Some((roll, result))) => Blow {
+                striker: self.target,
+                struck: self.attacker,
+                from: self.to,
+                to: self.from,
+                roll,
+                result,
+                melee: self.counter_melee,
+            },
+            _ => Blow {
+                striker: self.attacker,
+                struck: self.target,
+                from: self.from,
+                to: self.to,
+                roll: self.exchange.roll,
+                result: self.exchange.attack,
+                melee: self.melee,
+            },
+        }
+    }
+
+    /// Whether the counter follows once the current blow is over.
+    fn counter_follows(&self) -> bool {
+        !self.countering && !self.killed && self.exchange.counter.is_some()
+    }
+
+    fn duration(&self) -> f32 {
+        let after_hit = if self.killed {
+            f32::max(RECOVER_TIME, DEATH_TIME)
+        } else {
+            RECOVER_TIME
+        };
+        STRIKE_TIME + after_hit
+    }
+
+    fn lunge(&self) -> f32 {
+        if !self.blow().melee {
+            0.0
+        } else if self.elapsed < STRIKE_TIME {
+            self.elapsed / STRIKE_TIME
+        } else {
+            (1.0 - (self.elapsed - STRIKE_TIME) / RECOVER_TIME).max(0.0)
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PendingAttack(pub Option<AttackAnimation>);
+
+#[derive(Component)]
+struct Projectile;
+
+fn spawn_projectile(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    grid_config: &GridConfig,
+    from: (i32, i32),
+) -> Entity {
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: asset_server.load("sprites/fire.png"),
+            sprite: Sprite {
+                color: Color::ORANGE,
+                custom_size: Some(Vec2::splat(24.0)),
+                ..default()
+            },
+            transform: Transform::from_translation(grid_config.grid_to_world(from).extend(15.0)),
+            ..default()
+        })
+        .insert(Projectile)
+        .insert(LevelEntity)
+        .id()
+}
+
+/// Plays the pending attack and any counter, then hands the turn back to whoever attacked.
+fn play_attack(
+    mut commands: Commands,
+    time: Res<Time>,
+    playback: Res<Playback>,
+    asset_server: Res<AssetServer>,
+    grid_config: Res<GridConfig>,
+    mut pending: ResMut<PendingAttack>,
+    mut healths: Query<&mut Health>,
+    names: Query<&Name>,
+    mut sprites: Query<(
+        &Parent,
+        &UnitSprite,
+        &mut Handle<Image>,
+        &mut Transform,
+        &mut Sprite,
+    )>,
+    mut projectiles: Query<&mut Transform, (With<Projectile>, Without<UnitSprite>)>,
+    mut damage_dealt: EventWriter<DamageDealt>,
+    mut phase: ResMut<State<TurnPhase>>,
+) {
+    let next = if *phase.current() == TurnPhase::DoAttack {
+        TurnPhase::SelectUnit
+    } else {
+        TurnPhase::AISelectUnit
+    };
+    let attack = match pending.0.as_mut() {
+        Some(attack) => attack,
+        None => {
+            phase.set(next).unwrap();
+            return;
+        }
+    };
+    attack.elapsed += time.delta_seconds() * playback.speed();
+    if attack.counter_follows() && attack.elapsed >= attack.duration() {
+        attack.elapsed -= attack.duration();
+        attack.countering = true;
+        attack.hit = false;
+    }
+    let blow = attack.blow();
+    let from = grid_config.grid_to_world(blow.from);
+    let to = grid_config.grid_to_world(blow.to);
+
+    if !blow.melee && !attack.hit && attack.projectile.is_none() {
+        attack.projectile = Some(spawn_projectile(
+            &mut commands,
+            &asset_server,
+            &grid_config,
+            blow.from,
+        ));
+    }
+    if let Some(mut transform) = attack.projectile.and_then(|p| projectiles.get_mut(p).ok()) {
+        let progress = (attack.elapsed / STRIKE_TIME).min(1.0);
+        transform.translation = from.lerp(to, progress).extend(transform.translation.z);
+    }
+
+    if !attack.hit && attack.elapsed >= STRIKE_TIME {
+        attack.hit = true;
+        if let Some(projectile) = attack.projectile.take() {
+            commands.entity(projectile).despawn_recursive();
+        }
+        if let Ok(mut health) = healths.get_mut(blow.struck) {
+            let result = blow.result;
+            health.value = result.remaining;
+            attack.killed = result.killed;
+            let name = |unit: Entity| names.get(unit).map_or("A unit", |name| name.as_str());
+            if !blow.roll.hit {
+                info!("{} misses {}", name(blow.striker), name(blow.struck));
+            } else {
+                let verb = if attack.countering {
+                    "strikes back at"
+                } else {
+                    "hits"
+                };
+                let crit = if blow.roll.crit {
+                    ", a critical hit"
+                } else {
+                    ""
+                };
+                info!(
+                    "{} {} {} for {}{}",
+                    name(blow.striker),
+                    verb,
+                    name(blow.struck),
+                    result.damage,
+                    crit
+                );
+            }
+            if result.killed {
+                info!("{} is defeated", name(blow.struck));
+            }
+            damage_dealt.send(DamageDealt {
+                tile: blow.to,
+                damage: result.damage,
+                hit: blow.roll.hit,
+                crit: blow.roll.crit,
+            });
+        }
+    }
+
+    let finished = attack.elapsed >= attack.duration() && !attack.counter_follows();
+    let direction = (to - from).normalize_or_zero();
+    for (parent, unit_sprite, mut texture, mut transform, mut sprite) in sprites.iter_mut() {
+        if parent.get() == blow.struck && attack.killed {
+            let fade = ((attack.elapsed - STRIKE_TIME) / DEATH_TIME).clamp(0.0, 1.0);
+            sprite.color.set_a(1.0 - fade);
+            transform.scale = Vec3::splat(1.0 - 0.5 * fade);
+        } else if parent.get() == attack.attacker || parent.get() == attack.target {
+            let striking = parent.get() == blow.striker && !finished;
+            let wanted = if striking {
+                &unit_sprite.attack
+            } else {
+                &unit_sprite.idle
+            };
+            if *texture != *wanted {
+                *texture = wanted.clone();
+            }
+            let lunge = if striking { attack.lunge() } else { 0.0 };
+            let offset = direction * LUNGE_DISTANCE * lunge;
+            transform.translation = offset.extend(transform.translation.z);
+        }
+    }
+
+    if finished {
+        if attack.killed {
+            commands.entity(blow.struck).despawn_recursive();
+        }
+        pending.0 = None;
+        phase.set(next).unwrap();
+    }
+}
+
+impl Plugin for AttacksPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingAttack>()
+            .add_system_set(SystemSet::on_update(TurnPhase::DoAttack).with_system(play_attack))
+            .add_system_set(SystemSet::on_update(TurnPhase::AIDoAttack).with_system(play_attack));
+    }
+}
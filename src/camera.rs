@@ -1,4 +1,11 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, render::camera::RenderTarget};
+
+use crate::{
+    grid::GridConfig,
+    replay::Playback,
+    units::{TileClick, TileHover},
+};
+
 pub struct CameraPlugin;
 
 #[derive(Component)]
@@ -10,8 +17,55 @@ fn setup(mut commands: Commands) {
         .insert(MainCamera);
 }
 
+/// World position under the cursor, if the cursor is inside the camera's window.
+pub fn cursor_world_position(
+    windows: &Windows,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec2> {
+    // get the window that the camera is displaying to (or the primary window)
+    let wnd = if let RenderTarget::Window(id) = camera.target {
+        windows.get(id)?
+    } else {
+        windows.get_primary()?
+    };
+
+    // check if the cursor is inside the window and get its position
+    let screen_pos = wnd.cursor_position()?;
+    // get the size of the window
+    let window_size = Vec2::new(wnd.width(), wnd.height());
+    // convert screen position [0..resolution] to ndc [-1..1] (gpu coordinates)
+    let ndc = (screen_pos / window_size) * 2.0 - Vec2::ONE;
+    // matrix for undoing the projection and camera transform
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
+    // use it to convert ndc to world-space coordinates
+    let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
+    // reduce it to a 2D value
+    Some(world_pos.truncate())
+}
+
+/// Tracks the tile under the cursor and turns a left click into it for the game systems
+/// to act on.
+fn read_tile_click(
+    mouse_input: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    grid_config: Res<GridConfig>,
+    mut click: ResMut<TileClick>,
+    mut hover: ResMut<TileHover>,
+    playback: Res<Playback>,
+) {
+    let (camera, camera_transform) = q_camera.single();
+    hover.tile = grid_config.cursor_to_tile(&windows, camera, camera_transform);
+    // a running replay does the clicking
+    if mouse_input.just_pressed(MouseButton::Left) && !playback.is_playing() {
+        click.tile = hover.tile;
+    }
+}
+
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system_to_stage(StartupStage::PreStartup, setup);
+        app.add_startup_system_to_stage(StartupStage::PreStartup, setup)
+            .add_system_to_stage(CoreStage::PreUpdate, read_tile_click);
     }
 }
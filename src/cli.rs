@@ -0,0 +1,33 @@
+use bevy::prelude::*;
+
+/// Command line options, `--seed <n>` and `--replay <file>`, also accepted as `--flag=value`.
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct Args {
+    pub seed: Option<u64>,
+    pub replay: Option<String>,
+}
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Args {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            match flag.as_str() {
+                "--seed" => match value.or_else(|| args.next()).map(|value| value.parse()) {
+                    Some(Ok(seed)) => parsed.seed = Some(seed),
+                    _ => warn!("--seed expects a number, ignoring it"),
+                },
+                "--replay" => match value.or_else(|| args.next()) {
+                    Some(path) => parsed.replay = Some(path),
+                    None => warn!("--replay expects a file, ignoring it"),
+                },
+                _ => warn!("unknown argument {}", flag),
+            }
+        }
+        parsed
+    }
+}
@@ -0,0 +1,410 @@
+use crate::level::{enemy_path, roster_path, Level, Roster, UnitJson, LEVEL_PATH};
+use bevy::{asset::AssetServerSettings, prelude::*};
+use serde::de::DeserializeOwned;
+use std::{fmt, fs, path::Path};
+
+const ASSET_FOLDER: &str = "assets";
+
+pub struct ContentPlugin;
+
+/// A problem found in a data file, `field` is the JSON path inside it.
+#[derive(Debug)]
+pub struct ContentError {
+    pub path: String,
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}: {}: {}",
+            ASSET_FOLDER, self.path, self.field, self.message
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct ContentErrors(pub Vec<ContentError>);
+
+struct Report<'a> {
+    assets: &'a Path,
+    path: &'a str,
+    errors: &'a mut Vec<ContentError>,
+}
+
+impl<'a> Report<'a> {
+    fn error(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(ContentError {
+            path: self.path.to_string(),
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+}
+
+/// Reads and deserializes a data file, reporting the field serde stopped at.
+fn read<T: DeserializeOwned>(
+    assets: &Path,
+    path: &str,
+    errors: &mut Vec<ContentError>,
+) -> Option<T> {
+    let mut report = Report {
+        assets,
+        path,
+        errors,
+    };
+    let text = match fs::read_to_string(assets.join(path)) {
+        Ok(text) => text,
+        Err(err) => {
+            report.error(".", format!("cannot read file ({})", err));
+            return None;
+        }
+    };
+    let deserializer = &mut serde_json::Deserializer::from_str(&text);
+    match serde_path_to_error::deserialize(deserializer) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            report.error(err.path().to_string(), err.inner().to_string());
+            None
+        }
+    }
+}
+
+fn check_unit(unit: &UnitJson, prefix: &str, report: &mut Report) {
+    if !report.assets.join("sprites").join(&unit.sprite).is_file() {
+        report.error(
+            format!("{}sprite", prefix),
+            format!("no sprite named {}", unit.sprite),
+        );
+    }
+    let attack_sprite = unit.attack_sprite();
+    if !report.assets.join("sprites").join(&attack_sprite).is_file() {
+        report.error(
+            format!("{}attack_sprite", prefix),
+            format!("no sprite named {}", attack_sprite),
+        );
+    }
+    if unit.movement < 0 {
+        report.error(format!("{}movement", prefix), "must not be negative");
+    }
+    if unit.health < 1 {
+        report.error(format!("{}health", prefix), "must be at least 1");
+    }
+    if unit.damage < 0 {
+        report.error(format!("{}damage", prefix), "must not be negative");
+    }
+    if unit.max_damage() < unit.damage {
+        report.error(format!("{}max_damage", prefix), "must not be below damage");
+    }
+    if unit.range < 1 {
+        report.error(format!("{}range", prefix), "must be at least 1");
+    }
+    if unit.defense < 0 {
+        report.error(format!("{}defense", prefix), "must not be negative");
+    }
+    for (field, percent) in [
+        ("accuracy", unit.accuracy),
+        ("evasion", unit.evasion),
+        ("crit", unit.crit),
+    ] {
+        if !(0..=100).contains(&percent) {
+            report.error(format!("{}{}", prefix, field), "must be between 0 and 100");
+        }
+    }
+    if unit.vision < 1 {
+        report.error(format!("{}vision", prefix), "must be at least 1");
+    }
+}
+
+fn check_level(level: &Level, roster: Option<&Roster>, report: &mut Report) {
+    if level.width < 1 {
+        report.error("width", "must be at least 1");
+    }
+    if level.height < 1 {
+        report.error("height", "must be at least 1");
+    }
+    let Level { width, height, .. } = *level;
+    let on_board = |(x, y): (i32, i32)| x >= 0 && y >= 0 && x < width && y < height;
+
+    if let Some(map) = &level.map {
+        if map.tiles.len() as i32 != height {
+            report.error(
+                "map.tiles",
+                format!("has {} rows, height is {}", map.tiles.len(), height),
+            );
+        }
+        for (i, row) in map.tiles.iter().enumerate() {
+            if row.chars().count() as i32 != width {
+                report.error(
+                    format!("map.tiles[{}]", i),
+                    format!("has {} tiles, width is {}", row.chars().count(), width),
+                );
+            }
+        }
+        for (field, spawns) in [
+            ("player_spawns", &map.player_spawns),
+            ("ai_spawns", &map.ai_spawns),
+        ] {
+            for (i, &spawn) in spawns.iter().enumerate() {
+                if !on_board(spawn) {
+                    report.error(format!("map.{}[{}]", field, i), "is outside the board");
+                } else if map.is_blocked(spawn.0, spawn.1) {
+                    report.error(format!("map.{}[{}]", field, i), "is on an obstacle");
+                }
+            }
+        }
+        for (zone, tiles) in map.spawn_zones.iter() {
+            if tiles.is_empty() {
+                report.error(
+                    format!("map.spawn_zones.{}", zone),
+                    "needs at least one tile",
+                );
+            }
+            for (i, &tile) in tiles.iter().enumerate() {
+                if !on_board(tile) {
+                    report.error(
+                        format!("map.spawn_zones.{}[{}]", zone, i),
+                        "is outside the board",
+                    );
+                } else if map.is_blocked(tile.0, tile.1) {
+                    report.error(
+                        format!("map.spawn_zones.{}[{}]", zone, i),
+                        "is on an obstacle",
+                    );
+                }
+            }
+        }
+        if map.ai_spawns.is_empty() {
+            report.error("map.ai_spawns", "needs at least one spawn");
+        }
+        if let Some(roster) = roster {
+            if map.player_spawns.len() < roster.units.len() {
+                report.error(
+                    "map.player_spawns",
+                    format!(
+                        "has {} spawns for {} roster units",
+                        map.player_spawns.len(),
+                        roster.units.len()
+                    ),
+                );
+            }
+        }
+    }
+    if level.waves.is_empty() {
+        report.error("waves", "needs at least one wave");
+    }
+    for (i, wave) in level.waves.iter().enumerate() {
+        for (j, wave_unit) in wave.iter().enumerate() {
+            if wave_unit.count < 1 {
+                report.error(format!("waves[{}][{}].count", i, j), "must be at least 1");
+            }
+            if let Some(zone) = &wave_unit.zone {
+                if !matches!(&level.map, Some(map) if map.spawn_zones.contains_key(zone)) {
+                    report.error(
+                        format!("waves[{}][{}].zone", i, j),
+                        format!("unknown spawn zone {}", zone),
+                    );
+                }
+            }
+            if !report.assets.join(enemy_path(&wave_unit.unit)).is_file() {
+                report.error(
+                    format!("waves[{}][{}].unit", i, j),
+                    format!("unknown unit {}", wave_unit.unit),
+                );
+            }
+        }
+    }
+}
+
+/// Checks the level and every roster and enemy file it refers to.
+pub fn validate_content(assets: &Path, level_path: &str) -> Vec<ContentError> {
+    let mut errors = Vec::new();
+    let level: Level = match read(assets, level_path, &mut errors) {
+        Some(level) => level,
+        None => return errors,
+    };
+
+    let path = roster_path(&level.roster);
+    let roster: Option<Roster> = if assets.join(&path).is_file() {
+        read(assets, &path, &mut errors)
+    } else {
+        Report {
+            assets,
+            path: level_path,
+            errors: &mut errors,
+        }
+        .error("roster", format!("unknown roster {}", level.roster));
+        None
+    };
+    if let Some(roster) = &roster {
+        let mut report = Report {
+            assets,
+            path: &path,
+            errors: &mut errors,
+        };
+        if roster.units.is_empty() {
+            report.error("units", "needs at least one unit");
+        }
+        for (i, unit) in roster.units.iter().enumerate() {
+            check_unit(unit, &format!("units[{}].", i), &mut report);
+        }
+    }
+
+    check_level(
+        &level,
+        roster.as_ref(),
+        &mut Report {
+            assets,
+            path: level_path,
+            errors: &mut errors,
+        },
+    );
+
+    let mut names: Vec<&String> = level.waves.iter().flatten().map(|w| &w.unit).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let path = enemy_path(name);
+        if !assets.join(&path).is_file() {
+            continue;
+        }
+        if let Some(unit) = read::<UnitJson>(assets, &path, &mut errors) {
+            check_unit(
+                &unit,
+                "",
+                &mut Report {
+                    assets,
+                    path: &path,
+                    errors: &mut errors,
+                },
+            );
+        }
+    }
+    errors
+}
+
+// the same folder the asset server reads the data from
+fn check_content(settings: Res<AssetServerSettings>, mut content_errors: ResMut<ContentErrors>) {
+    content_errors.0 = validate_content(Path::new(&settings.asset_folder), LEVEL_PATH);
+    for err in content_errors.0.iter() {
+        error!("{}", err);
+    }
+}
+
+impl Plugin for ContentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ContentErrors>()
+            .add_startup_system(check_content);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{level::LevelPlugin, states::TurnPhase};
+    use bevy::{asset::AssetPlugin, core::CorePlugin};
+    use serde_json::{json, Value};
+    use std::{path::PathBuf, process, thread, time::Duration};
+
+    /// Copy of the game's data files in a folder of its own, with one file replaced.
+    fn fixture(name: &str, replace: &str, file: Option<Value>) -> PathBuf {
+        let assets = std::env::temp_dir().join(format!("tbt-content-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&assets);
+        for folder in ["sprites", "data/levels", "data/rosters", "data/enemies"] {
+            fs::create_dir_all(assets.join(folder)).unwrap();
+            for entry in fs::read_dir(Path::new(ASSET_FOLDER).join(folder)).unwrap() {
+                let entry = entry.unwrap();
+                fs::copy(entry.path(), assets.join(folder).join(entry.file_name())).unwrap();
+            }
+        }
+        match file {
+            Some(value) => fs::write(assets.join(replace), value.to_string()).unwrap(),
+            None => fs::remove_file(assets.join(replace)).unwrap(),
+        }
+        assets
+    }
+
+    fn level_with(field: &str, value: Value) -> Option<Value> {
+        let text = fs::read_to_string(Path::new(ASSET_FOLDER).join(LEVEL_PATH)).unwrap();
+        let mut level: Value = serde_json::from_str(&text).unwrap();
+        level[field] = value;
+        Some(level)
+    }
+
+    /// Runs the loading screen on the fixture, it has to stop at the error screen.
+    fn assert_stops_loading(assets: &Path) {
+        let mut app = App::new();
+        app.insert_resource(AssetServerSettings {
+            asset_folder: assets.display().to_string(),
+            ..default()
+        })
+        .add_plugin(CorePlugin)
+        .add_plugin(AssetPlugin)
+        .add_plugin(ContentPlugin)
+        .add_plugin(LevelPlugin)
+        .add_state(TurnPhase::LoadAssets);
+        for _ in 0..100 {
+            app.update();
+            thread::sleep(Duration::from_millis(1));
+        }
+        let phase = *app.world.resource::<State<TurnPhase>>().current();
+        assert_eq!(phase, TurnPhase::ContentError);
+        fs::remove_dir_all(assets).unwrap();
+    }
+
+    fn messages(assets: &Path) -> Vec<String> {
+        validate_content(assets, LEVEL_PATH)
+            .iter()
+            .map(|err| err.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn the_shipped_content_is_valid() {
+        assert!(messages(Path::new(ASSET_FOLDER)).is_empty());
+    }
+
+    #[test]
+    fn a_malformed_level_field_names_the_field() {
+        let assets = fixture("field", LEVEL_PATH, level_with("width", json!("nine")));
+        let messages = messages(&assets);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with(
+            "assets/data/levels/001.json: width: invalid type: string \"nine\", expected i32"
+        ));
+        assert_stops_loading(&assets);
+    }
+
+    #[test]
+    fn a_missing_enemy_file_is_an_unknown_unit() {
+        let assets = fixture("enemy", &enemy_path("zombie"), None);
+        assert_eq!(
+            messages(&assets),
+            vec!["assets/data/levels/001.json: waves[0][1].unit: unknown unit zombie"]
+        );
+        assert_stops_loading(&assets);
+    }
+
+    #[test]
+    fn waves_name_known_units() {
+        let waves = json!([[{ "unit": "dragon", "count": 1 }]]);
+        let assets = fixture("wave", LEVEL_PATH, level_with("waves", waves));
+        assert_eq!(
+            messages(&assets),
+            vec!["assets/data/levels/001.json: waves[0][0].unit: unknown unit dragon"]
+        );
+        assert_stops_loading(&assets);
+    }
+
+    #[test]
+    fn the_roster_has_to_exist() {
+        let assets = fixture("roster", LEVEL_PATH, level_with("roster", json!("ninjas")));
+        assert_eq!(
+            messages(&assets),
+            vec!["assets/data/levels/001.json: roster: unknown roster ninjas"]
+        );
+        assert_stops_loading(&assets);
+    }
+}
@@ -0,0 +1,185 @@
+use bevy::{prelude::*, render::view::VisibilitySystems};
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ai_units::Ai,
+    grid::{GridConfig, GridPosition, LevelEntity, Tile},
+    sim::{visible_tiles, Board, Coord, Team},
+    states::TurnPhase,
+    units::{Unit, UnitSprite, Vision},
+};
+
+pub struct FogPlugin;
+
+const FOG_ALPHA: f32 = 0.6;
+const GHOST_ALPHA: f32 = 0.35;
+
+/// Darkens a tile the player's units cannot see, a child of the tile.
+#[derive(Component)]
+pub struct Fog;
+
+/// Faded copy of an enemy on the tile the player last saw it on.
+#[derive(Component)]
+pub struct Ghost {
+    pub unit: Entity,
+    pub tile: Coord,
+}
+
+/// Tiles each side sees, updated at the end of every frame and before each ai decision.
+#[derive(Default, Debug)]
+pub struct TeamVision {
+    pub player: HashSet<Coord>,
+    pub ai: HashSet<Coord>,
+}
+
+impl TeamVision {
+    pub fn sees(&self, team: Team, tile: Coord) -> bool {
+        match team {
+            Team::PLAYER => self.player.contains(&tile),
+            Team::AI => self.ai.contains(&tile),
+        }
+    }
+}
+
+/// Where the player last saw each enemy that has gone out of sight.
+#[derive(Default)]
+pub struct LastSeen(pub HashMap<Entity, Coord>);
+
+/// Tiles seen by the given units together.
+pub fn sight<'a>(
+    board: &Board,
+    viewers: impl IntoIterator<Item = (&'a GridPosition, &'a Vision)>,
+) -> HashSet<Coord> {
+    let viewers: Vec<(Coord, i32)> = viewers
+        .into_iter()
+        .map(|(grid, vision)| ((grid.x, grid.y), vision.radius))
+        .collect();
+    visible_tiles(board, &viewers)
+}
+
+pub fn update_vision(
+    board: Res<Board>,
+    units: Query<(&Unit, &GridPosition, &Vision)>,
+    mut vision: ResMut<TeamVision>,
+) {
+    let team_sight = |team: Team| {
+        sight(
+            &board,
+            units
+                .iter()
+                .filter(|(unit, _grid, _vision)| unit.team == team)
+                .map(|(_unit, grid, vision)| (grid, vision)),
+        )
+    };
+    vision.player = team_sight(Team::PLAYER);
+    vision.ai = team_sight(Team::AI);
+}
+
+fn draw_fog(
+    vision: Res<TeamVision>,
+    tiles: Query<&GridPosition, With<Tile>>,
+    mut fog: Query<(&Parent, &mut Sprite), With<Fog>>,
+) {
+    if !vision.is_changed() {
+        return;
+    }
+    for (parent, mut sprite) in fog.iter_mut() {
+        if let Ok(grid) = tiles.get(parent.get()) {
+            let alpha = if vision.player.contains(&(grid.x, grid.y)) {
+                0.0
+            } else {
+                FOG_ALPHA
+            };
+            sprite.color.set_a(alpha);
+        }
+    }
+}
+
+fn hide_enemies(
+    vision: Res<TeamVision>,
+    mut ai_units: Query<(&GridPosition, &mut Visibility), With<Ai>>,
+) {
+    for (grid, mut visibility) in ai_units.iter_mut() {
+        let seen = vision.player.contains(&(grid.x, grid.y));
+        if visibility.is_visible != seen {
+            visibility.is_visible = seen;
+        }
+    }
+}
+
+/// Leaves a ghost where an enemy was last seen, until the player looks at that tile again.
+fn update_ghosts(
+    mut commands: Commands,
+    vision: Res<TeamVision>,
+    grid_config: Res<GridConfig>,
+    mut last_seen: ResMut<LastSeen>,
+    ai_units: Query<(Entity, &GridPosition, &Children), With<Ai>>,
+    sprites: Query<&UnitSprite>,
+    ghosts: Query<(Entity, &Ghost)>,
+) {
+    let mut in_sight = HashSet::new();
+    for (unit, grid, children) in ai_units.iter() {
+        if vision.player.contains(&(grid.x, grid.y)) {
+            last_seen.0.insert(unit, (grid.x, grid.y));
+            in_sight.insert(unit);
+            continue;
+        }
+        let tile = match last_seen.0.get(&unit) {
+            Some(tile) => *tile,
+            None => continue,
+        };
+        if vision.player.contains(&tile) {
+            last_seen.0.remove(&unit);
+            continue;
+        }
+        let haunted = ghosts
+            .iter()
+            .any(|(_e, ghost)| ghost.unit == unit && ghost.tile == tile);
+        let texture = children.iter().find_map(|child| sprites.get(*child).ok());
+        if let (false, Some(texture)) = (haunted, texture) {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    texture: texture.idle.clone(),
+                    sprite: Sprite {
+                        color: Color::rgba(1.0, 1.0, 1.0, GHOST_ALPHA),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(
+                        grid_config.grid_to_world(tile).extend(5.0),
+                    ),
+                    ..default()
+                })
+                .insert(Ghost { unit, tile })
+                .insert(LevelEntity);
+        }
+    }
+    last_seen.0.retain(|unit, _tile| ai_units.contains(*unit));
+    for (entity, ghost) in ghosts.iter() {
+        if in_sight.contains(&ghost.unit) || last_seen.0.get(&ghost.unit) != Some(&ghost.tile) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn forget_enemies(mut last_seen: ResMut<LastSeen>) {
+    last_seen.0.clear();
+}
+
+impl Plugin for FogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TeamVision>()
+            .init_resource::<LastSeen>()
+            // after the frame's moves, so the next frame's decisions see where units ended up
+            .add_system_to_stage(CoreStage::PostUpdate, update_vision)
+            .add_system_to_stage(CoreStage::PostUpdate, draw_fog.after(update_vision))
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                hide_enemies
+                    .after(update_vision)
+                    .before(VisibilitySystems::VisibilityPropagate),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, update_ghosts.after(update_vision))
+            .add_system_set(SystemSet::on_enter(TurnPhase::LoadLevel).with_system(forget_enemies))
+            .add_system_set(SystemSet::on_enter(TurnPhase::LoadSave).with_system(forget_enemies));
+    }
+}
@@ -0,0 +1,221 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    ai_units::{DelayedSpawns, WaveIndex},
+    attacks::PendingAttack,
+    content::ContentErrors,
+    grid::{LevelEntity, SelectedPath},
+    pathfinding::AllUnitsActed,
+    sim::Board,
+    states::TurnPhase,
+    units::{ActiveUnit, SelectedUnit},
+};
+
+pub struct GameOverPlugin;
+
+const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON: Color = Color::rgb(0.3, 0.3, 0.3);
+
+#[derive(Component)]
+struct EndScreen;
+
+#[derive(Component, Clone, Copy)]
+enum EndScreenButton {
+    Restart,
+    Quit,
+}
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_enter(TurnPhase::LoadLevel)
+                .with_system(despawn_level)
+                .with_system(reset_level),
+        )
+        .add_system_set(
+            SystemSet::on_enter(TurnPhase::LoadSave)
+                .with_system(despawn_level)
+                .with_system(reset_level),
+        )
+        .add_system_set(SystemSet::on_enter(TurnPhase::Victory).with_system(victory_screen))
+        .add_system_set(SystemSet::on_enter(TurnPhase::Defeat).with_system(defeat_screen))
+        .add_system_set(SystemSet::on_update(TurnPhase::Victory).with_system(end_screen_buttons))
+        .add_system_set(SystemSet::on_update(TurnPhase::Defeat).with_system(end_screen_buttons))
+        .add_system_set(SystemSet::on_exit(TurnPhase::Victory).with_system(despawn_end_screen))
+        .add_system_set(SystemSet::on_exit(TurnPhase::Defeat).with_system(despawn_end_screen))
+        .add_system_set(
+            SystemSet::on_enter(TurnPhase::ContentError).with_system(content_error_screen),
+        )
+        .add_system_set(
+            SystemSet::on_update(TurnPhase::ContentError).with_system(end_screen_buttons),
+        );
+    }
+}
+
+pub fn despawn_level(mut commands: Commands, level_entities: Query<Entity, With<LevelEntity>>) {
+    for entity in level_entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub fn reset_level(
+    mut wave_index: ResMut<WaveIndex>,
+    mut delayed: ResMut<DelayedSpawns>,
+    mut board: ResMut<Board>,
+    mut selected_path: ResMut<SelectedPath>,
+    mut active: ResMut<ActiveUnit>,
+    mut selected: ResMut<SelectedUnit>,
+    mut all_acted: ResMut<AllUnitsActed>,
+    mut pending_attack: ResMut<PendingAttack>,
+) {
+    *wave_index = WaveIndex::default();
+    *delayed = DelayedSpawns::default();
+    *board = Board::default();
+    *selected_path = SelectedPath::default();
+    *active = ActiveUnit::default();
+    *selected = SelectedUnit::default();
+    *all_acted = AllUnitsActed::default();
+    *pending_attack = PendingAttack::default();
+}
+
+fn victory_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_end_screen(
+        &mut commands,
+        &asset_server,
+        "Victory",
+        Color::GOLD,
+        &[],
+        &[EndScreenButton::Restart, EndScreenButton::Quit],
+    );
+}
+
+fn defeat_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_end_screen(
+        &mut commands,
+        &asset_server,
+        "Defeat",
+        Color::RED,
+        &[],
+        &[EndScreenButton::Restart, EndScreenButton::Quit],
+    );
+}
+
+/// Lists every problem found in the data files, the game cannot start until they are fixed.
+fn content_error_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    content_errors: Res<ContentErrors>,
+) {
+    let lines: Vec<String> = content_errors.0.iter().map(|err| err.to_string()).collect();
+    spawn_end_screen(
+        &mut commands,
+        &asset_server,
+        "Content errors",
+        Color::RED,
+        &lines,
+        &[EndScreenButton::Quit],
+    );
+}
+
+fn spawn_end_screen(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    title: &str,
+    title_color: Color,
+    lines: &[String],
+    buttons: &[EndScreenButton],
+) {
+    let font = asset_server.load("fonts/SourceCodePro.ttf");
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+            ..default()
+        })
+        .insert(EndScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(
+                TextBundle::from_section(
+                    title,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 64.0,
+                        color: title_color,
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::all(Val::Px(20.0)),
+                    ..default()
+                }),
+            );
+            for line in lines {
+                parent.spawn_bundle(TextBundle::from_section(
+                    line,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 18.0,
+                        color: Color::WHITE,
+                    },
+                ));
+            }
+            for &button in buttons {
+                let label = match button {
+                    EndScreenButton::Restart => "Restart",
+                    EndScreenButton::Quit => "Quit",
+                };
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(200.0), Val::Px(50.0)),
+                            margin: UiRect::all(Val::Px(10.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        color: NORMAL_BUTTON.into(),
+                        ..default()
+                    })
+                    .insert(button)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle::from_section(
+                            label,
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 32.0,
+                                color: Color::WHITE,
+                            },
+                        ));
+                    });
+            }
+        });
+}
+
+fn end_screen_buttons(
+    mut interactions: Query<(&Interaction, &EndScreenButton, &mut UiColor), Changed<Interaction>>,
+    mut phase: ResMut<State<TurnPhase>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for (interaction, button, mut color) in interactions.iter_mut() {
+        match interaction {
+            Interaction::Clicked => match button {
+                EndScreenButton::Restart => phase.set(TurnPhase::LoadLevel).unwrap(),
+                EndScreenButton::Quit => exit.send(AppExit),
+            },
+            Interaction::Hovered => *color = HOVERED_BUTTON.into(),
+            Interaction::None => *color = NORMAL_BUTTON.into(),
+        }
+    }
+}
+
+fn despawn_end_screen(mut commands: Commands, screens: Query<Entity, With<EndScreen>>) {
+    for entity in screens.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
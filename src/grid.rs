@@ -1,12 +1,15 @@
 use crate::{
-    pathfinding::calculate_a_star_path,
+    camera::cursor_world_position,
+    fog::{Fog, TeamVision},
+    level::{Level, LevelHandles},
     player_units::Player,
+    rng::GameRng,
+    sim::{in_attack_range, Board, Terrain},
     states::TurnPhase,
     units::{ActiveUnit, Attack, Health, Movement, SelectedUnit, Spawners, Unit},
 };
 use bevy::prelude::*;
 use rand::Rng;
-use std::collections::HashMap;
 pub struct GridPlugin;
 
 #[derive(Component, Debug)]
@@ -15,14 +18,29 @@ pub struct GridPosition {
     pub y: i32,
 }
 
+fn terrain_sprite(terrain: Terrain) -> &'static str {
+    match terrain {
+        Terrain::Grass => "sprites/tile.png",
+        Terrain::Mud => "sprites/mud.png",
+        Terrain::Water => "sprites/water.png",
+        Terrain::Road => "sprites/road.png",
+        Terrain::Rubble => "sprites/rubble.png",
+    }
+}
+
 #[derive(Component, Debug)]
 pub struct Tile {
     pub blocked: bool,
+    pub terrain: Terrain,
 }
 
 #[derive(Component)]
 pub struct Obstacle;
 
+/// Root of entities that belong to the current level and are despawned on restart.
+#[derive(Component)]
+pub struct LevelEntity;
+
 #[derive(Default, Debug)]
 pub struct SelectedPath {
     pub tiles: Vec<(i32, i32)>,
@@ -31,15 +49,46 @@ pub struct SelectedPath {
 #[derive(Default)]
 pub struct GridConfig {
     pub tile_size: f32,
-    pub rows_cols: i32,
+    pub width: i32,
+    pub height: i32,
 }
 
-#[derive(Default)]
-pub struct BlockedTiles(pub HashMap<(i32, i32), bool>);
-
 impl GridConfig {
-    pub fn offset(&self) -> f32 {
-        self.tile_size * (self.rows_cols as f32 * 0.5)
+    pub fn offset_x(&self) -> f32 {
+        self.tile_size * (self.width as f32 * 0.5)
+    }
+
+    pub fn offset_y(&self) -> f32 {
+        self.tile_size * (self.height as f32 * 0.5)
+    }
+
+    /// Centre of a tile in world space.
+    pub fn grid_to_world(&self, grid: (i32, i32)) -> Vec2 {
+        Vec2::new(
+            grid.0 as f32 * self.tile_size - self.offset_x(),
+            grid.1 as f32 * self.tile_size - self.offset_y(),
+        )
+    }
+
+    /// Tile whose bounds contain `world`, if it lies on the board.
+    pub fn world_to_grid(&self, world: Vec2) -> Option<(i32, i32)> {
+        let x = ((world.x + self.offset_x()) / self.tile_size + 0.5).floor() as i32;
+        let y = ((world.y + self.offset_y()) / self.tile_size + 0.5).floor() as i32;
+        if x >= 0 && x < self.width && y >= 0 && y < self.height {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    pub fn cursor_to_tile(
+        &self,
+        windows: &Windows,
+        camera: &Camera,
+        camera_transform: &GlobalTransform,
+    ) -> Option<(i32, i32)> {
+        cursor_world_position(windows, camera, camera_transform)
+            .and_then(|world| self.world_to_grid(world))
     }
 }
 
@@ -82,27 +131,37 @@ fn highlight_selected_unit(
         None => {}
     }
 }
+/// Enemies in range turn red, those out of sight behind an obstacle grey.
 fn highlight_attackable_tiles(
     mut tiles: Query<(&mut Tile, &GridPosition, &mut Sprite), With<Tile>>,
     ai_units: Query<(Entity, &GridPosition), (With<Health>, Without<Player>)>,
     player_units: Query<(Entity, &Attack, &Player, &GridPosition)>,
     active_res: Res<ActiveUnit>,
+    board: Res<Board>,
+    vision: Res<TeamVision>,
 ) {
     match active_res.value {
         Some(active) => match player_units.get(active) {
             Ok((_e, attack, _player, active_grid)) => {
-                for (_e, grid) in ai_units.into_iter() {
-                    let dist = std::cmp::max(
-                        i32::abs(grid.x - active_grid.x),
-                        i32::abs(grid.y - active_grid.y),
-                    );
-                    if dist > 0 && dist <= attack.range {
+                // enemies hidden in the fog are not given away
+                for (_e, grid) in ai_units
+                    .into_iter()
+                    .filter(|(_e, grid)| vision.player.contains(&(grid.x, grid.y)))
+                {
+                    let (from, to) = ((active_grid.x, active_grid.y), (grid.x, grid.y));
+                    if in_attack_range(from, to, attack.range) {
                         if let Some((_tile, _grid, mut sprite)) = tiles
                             .iter_mut()
                             .find(|(_t, g, _s)| g.x == grid.x && g.y == grid.y)
                         {
-                            sprite.color.set_b(0.0);
-                            sprite.color.set_g(0.0);
+                            if board.line_of_sight(from, to) {
+                                sprite.color.set_b(0.0);
+                                sprite.color.set_g(0.0);
+                            } else {
+                                sprite.color.set_r(0.5);
+                                sprite.color.set_g(0.5);
+                                sprite.color.set_b(0.5);
+                            }
                             sprite.color.set_a(1.0);
                         }
                     }
@@ -118,20 +177,17 @@ fn highlight_reachable_tiles(
     unit_grids: Query<(Entity, &GridPosition), Without<Tile>>,
     movements: Query<(Entity, &Movement)>,
     active_res: Res<ActiveUnit>,
-    blocked_res: Res<BlockedTiles>,
+    board: Res<Board>,
 ) {
     match active_res.value {
         Some(active) => match unit_grids.get(active) {
             Ok((_e, active_grid)) => match movements.get(active) {
                 Ok((_e, active_movement)) => {
+                    let reachable = board
+                        .reachable_tiles((active_grid.x, active_grid.y), active_movement.distance);
                     for (_tile, _grid, mut sprite) in tiles.iter_mut().filter(|(tile, grid, _s)| {
-                        let dist = calculate_a_star_path(
-                            (active_grid.x, active_grid.y),
-                            (grid.x, grid.y),
-                            &blocked_res,
-                        )
-                        .len() as i32;
-                        dist > 0 && dist <= active_movement.distance && !tile.blocked
+                        matches!(reachable.cost((grid.x, grid.y)), Some(cost) if cost > 0)
+                            && !tile.blocked
                     }) {
                         sprite.color.set_r(0.0);
                         sprite.color.set_b(0.0);
@@ -163,135 +219,170 @@ fn clear_highlighted_tiles(mut tiles: Query<&mut Sprite, With<Tile>>) {
     }
 }
 
-fn spawn_tile(
-    x: f32,
-    y: f32,
-    i: i32,
+pub fn spawn_tile(
+    world: Vec2,
+    grid: (i32, i32),
     commands: &mut Commands,
     asset_server: &Res<AssetServer>,
-    grid_config: &Res<GridConfig>,
     blocked: bool,
+    terrain: Terrain,
 ) -> Entity {
+    let texture = asset_server.load(if blocked {
+        "sprites/blocked.png"
+    } else {
+        terrain_sprite(terrain)
+    });
     let mut tile = commands.spawn_bundle(SpriteBundle {
-        texture: asset_server.load(if blocked {
-            "sprites/blocked.png"
-        } else {
-            "sprites/tile.png"
-        }),
-        transform: Transform::from_translation(Vec3::new(x, y, 0.0)),
+        texture: texture.clone(),
+        transform: Transform::from_translation(world.extend(0.0)),
         ..default()
     });
-    tile.insert(Name::new(format!(
-        "Tile ({},{})",
-        i / grid_config.rows_cols,
-        i % grid_config.rows_cols
-    )))
-    .insert(Tile { blocked })
-    .insert(GridPosition {
-        x: i / grid_config.rows_cols,
-        y: i % grid_config.rows_cols,
-    });
+    tile.insert(Name::new(format!("Tile ({},{})", grid.0, grid.1)))
+        .insert(Tile { blocked, terrain })
+        .insert(GridPosition {
+            x: grid.0,
+            y: grid.1,
+        });
     if blocked {
         tile.insert(Obstacle);
     }
+    // fog of war over the tile, drawn under the units
+    tile.with_children(|parent| {
+        parent
+            .spawn_bundle(SpriteBundle {
+                texture,
+                sprite: Sprite {
+                    color: Color::rgba(0.0, 0.0, 0.0, 0.0),
+                    ..default()
+                },
+                transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.5)),
+                ..default()
+            })
+            .insert(Fog);
+    });
 
-    return tile.id();
+    tile.id()
 }
 
-fn create_level(
+pub fn create_level(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    grid_config: Res<GridConfig>,
+    mut grid_config: ResMut<GridConfig>,
     mut spawners: ResMut<Spawners>,
+    mut rng: ResMut<GameRng>,
+    handles: Res<LevelHandles>,
+    levels: Res<Assets<Level>>,
 ) {
+    let level = levels
+        .get(&handles.level)
+        .expect("level should be loaded before LoadLevel");
+    grid_config.width = level.width;
+    grid_config.height = level.height;
+
     let mut tiles = Vec::new();
-    let mut rng = rand::thread_rng();
-    let positions = [
-        (
-            grid_config.rows_cols / 2 as i32,
-            grid_config.rows_cols / 2 as i32,
-        ),
-        (
-            grid_config.rows_cols / 2 as i32 - 1,
-            grid_config.rows_cols / 2 as i32,
-        ),
-        (
-            grid_config.rows_cols / 2 as i32 + 1,
-            grid_config.rows_cols / 2 as i32,
-        ),
-    ];
-    for i in 0..81 {
-        let x_ = i / grid_config.rows_cols;
-        let y_ = i % grid_config.rows_cols;
-        let x = (x_ as f32 * grid_config.tile_size) - grid_config.offset();
-        let y = (y_ as f32 * grid_config.tile_size) - grid_config.offset();
-        let chance = 0.25;
-        let roll = rng.gen_range(0.0..1.0);
-        let edge = x_ == 0
-            || y_ == 0
-            || x_ == grid_config.rows_cols - 1
-            || y_ == grid_config.rows_cols - 1;
-        let tile = spawn_tile(
-            x,
-            y,
-            i,
-            &mut commands,
-            &asset_server,
-            &grid_config,
-            !edge && !positions.contains(&(x_, y_)) && roll <= chance,
-        );
-        tiles.push(tile);
-        if edge {
-            spawners.ai_locations.push((x, y));
+    match &level.map {
+        Some(map) => {
+            for x_ in 0..grid_config.width {
+                for y_ in 0..grid_config.height {
+                    let terrain = map.terrain(x_, y_);
+                    let tile = spawn_tile(
+                        grid_config.grid_to_world((x_, y_)),
+                        (x_, y_),
+                        &mut commands,
+                        &asset_server,
+                        map.is_blocked(x_, y_),
+                        terrain,
+                    );
+                    tiles.push(tile);
+                }
+            }
+            spawners.player_locations = map.player_spawns.clone();
+            spawners.ai_locations = map.ai_spawns.clone();
+            spawners.zones = map.spawn_zones.clone();
+        }
+        None => {
+            let mut ai_locations = Vec::new();
+            let positions = [
+                (grid_config.width / 2, grid_config.height / 2),
+                (grid_config.width / 2 - 1, grid_config.height / 2),
+                (grid_config.width / 2 + 1, grid_config.height / 2),
+            ];
+            for x_ in 0..grid_config.width {
+                for y_ in 0..grid_config.height {
+                    let chance = 0.25;
+                    let roll = rng.gen_range(0.0..1.0);
+                    let edge = x_ == 0
+                        || y_ == 0
+                        || x_ == grid_config.width - 1
+                        || y_ == grid_config.height - 1;
+                    let tile = spawn_tile(
+                        grid_config.grid_to_world((x_, y_)),
+                        (x_, y_),
+                        &mut commands,
+                        &asset_server,
+                        !edge && !positions.contains(&(x_, y_)) && roll <= chance,
+                        Terrain::Grass,
+                    );
+                    tiles.push(tile);
+                    if edge {
+                        ai_locations.push((x_, y_));
+                    }
+                }
+            }
+            spawners.ai_locations = ai_locations;
+            spawners.zones.clear();
+            spawners.player_locations = positions.to_vec();
         }
     }
     commands
         .spawn()
         .insert(Name::new("MapTiles"))
+        .insert(LevelEntity)
         .insert_bundle(SpatialBundle::default())
         .push_children(&tiles);
 }
 
+fn start_level(mut phase: ResMut<State<TurnPhase>>) {
+    phase.set(TurnPhase::SelectUnit).unwrap();
+}
+
 fn set_blocked_tiles(
     units: Query<&GridPosition, With<Unit>>,
     obstacles: Query<&GridPosition, With<Obstacle>>,
     mut tiles: Query<(&GridPosition, &mut Tile)>,
-    mut blocked: ResMut<BlockedTiles>,
+    mut board: ResMut<Board>,
 ) {
+    let mut new_board = Board::default();
     for (tile_pos, mut tile) in tiles.iter_mut() {
-        if let Some(_unit_pos) = units
+        let occupied = units
             .into_iter()
-            .find(|u| u.x == tile_pos.x && u.y == tile_pos.y)
-        {
-            blocked.0.insert((tile_pos.x, tile_pos.y), true);
-            tile.blocked = true;
-        } else if let Some(_obs_pos) = obstacles
+            .any(|u| u.x == tile_pos.x && u.y == tile_pos.y);
+        let obstacle = obstacles
             .into_iter()
-            .find(|o| o.x == tile_pos.x && o.y == tile_pos.y)
-        {
-            blocked.0.insert((tile_pos.x, tile_pos.y), true);
-            tile.blocked = true;
-        } else {
-            blocked.0.insert((tile_pos.x, tile_pos.y), false);
-            tile.blocked = false;
+            .any(|o| o.x == tile_pos.x && o.y == tile_pos.y);
+        tile.blocked = occupied || obstacle;
+        new_board.set_tile((tile_pos.x, tile_pos.y), tile.blocked, tile.terrain.cost());
+        if obstacle {
+            new_board.set_obstacle((tile_pos.x, tile_pos.y));
         }
     }
+    *board = new_board;
 }
 impl Plugin for GridPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<SelectedPath>()
             .init_resource::<SelectedTile>()
-            .init_resource::<BlockedTiles>()
+            .init_resource::<Board>()
             .insert_resource(GridConfig {
                 tile_size: 64.0,
-                rows_cols: 9,
+                width: 9,
+                height: 9,
             })
-            .add_startup_system(create_level.before(crate::ai_units::spawn_wave))
             .add_system_set(
-                SystemSet::on_enter(TurnPhase::SelectAttacker)
-                    .with_system(clear_highlighted_tiles)
-                    .with_system(set_blocked_tiles.after(clear_highlighted_tiles)),
+                SystemSet::on_enter(TurnPhase::LoadLevel)
+                    .with_system(create_level.after(crate::game_over::reset_level)),
             )
+            .add_system_set(SystemSet::on_update(TurnPhase::LoadLevel).with_system(start_level))
             .add_system_set(
                 SystemSet::on_enter(TurnPhase::SelectUnit)
                     .with_system(clear_highlighted_tiles)
@@ -309,6 +400,11 @@ impl Plugin for GridPlugin {
                     .with_system(set_blocked_tiles.after(clear_highlighted_tiles))
                     .with_system(highlight_attackable_tiles.after(set_blocked_tiles)),
             )
+            .add_system_set(
+                SystemSet::on_enter(TurnPhase::DoAttack)
+                    .with_system(clear_highlighted_tiles)
+                    .with_system(set_blocked_tiles.after(clear_highlighted_tiles)),
+            )
             .add_system_set(
                 SystemSet::on_enter(TurnPhase::DoMove)
                     .with_system(clear_highlighted_tiles)
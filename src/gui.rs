@@ -1,8 +1,12 @@
 use bevy::prelude::*;
 
 use crate::{
+    player_units::AttackForecast,
+    replay::Playback,
+    rng::GameRng,
+    sim::Affinity,
     states::TurnPhase,
-    units::{Attack, Health, Movement, SelectedUnit, Unit},
+    units::{Attack, Defense, Health, Movement, SelectedUnit, Unit},
 };
 
 pub struct GuiPlugin;
@@ -21,6 +25,10 @@ struct SelectedUnitGUI {
     space: u32,
     escape: u32,
     range: u32,
+    odds: u32,
+    forecast: u32,
+    seed: u32,
+    replay: u32,
 }
 
 impl Plugin for GuiPlugin {
@@ -30,7 +38,10 @@ impl Plugin for GuiPlugin {
             .add_startup_system_to_stage(StartupStage::PreStartup, setup.after(pre_setup))
             .add_startup_system(get_labels)
             .add_system(current_state)
-            .add_system(selected_unit);
+            .add_system(selected_unit)
+            .add_system(forecast_text)
+            .add_system(seed_text)
+            .add_system(replay_text);
     }
 }
 fn pre_setup(mut commands: Commands) {
@@ -190,6 +201,20 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut gui: ResMut
                                             }),
                                         );
                                     });
+                                parent.spawn_bundle(
+                                    TextBundle::from_section(
+                                        "Odds",
+                                        TextStyle {
+                                            font: asset_server.load("fonts/SourceCodePro.ttf"),
+                                            font_size: 20.0,
+                                            color: Color::YELLOW,
+                                        },
+                                    )
+                                    .with_style(Style {
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        ..default()
+                                    }),
+                                );
                                 parent.spawn_bundle(
                                     TextBundle::from_section(
                                         "Can act",
@@ -261,6 +286,48 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut gui: ResMut
                                         ..default()
                                     }),
                                 );
+                                parent.spawn_bundle(
+                                    TextBundle::from_section(
+                                        "Forecast",
+                                        TextStyle {
+                                            font: asset_server.load("fonts/SourceCodePro.ttf"),
+                                            font_size: 20.0,
+                                            color: Color::ORANGE,
+                                        },
+                                    )
+                                    .with_style(Style {
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        ..default()
+                                    }),
+                                );
+                                parent.spawn_bundle(
+                                    TextBundle::from_section(
+                                        "Seed",
+                                        TextStyle {
+                                            font: asset_server.load("fonts/SourceCodePro.ttf"),
+                                            font_size: 16.0,
+                                            color: Color::GRAY,
+                                        },
+                                    )
+                                    .with_style(Style {
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        ..default()
+                                    }),
+                                );
+                                parent.spawn_bundle(
+                                    TextBundle::from_section(
+                                        "Replay",
+                                        TextStyle {
+                                            font: asset_server.load("fonts/SourceCodePro.ttf"),
+                                            font_size: 16.0,
+                                            color: Color::GRAY,
+                                        },
+                                    )
+                                    .with_style(Style {
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        ..default()
+                                    }),
+                                );
                             })
                             .id()
                             .id();
@@ -278,24 +345,33 @@ fn get_labels(mut texts: Query<(Entity, &mut Text)>, mut gui: ResMut<SelectedUni
             "Space" => gui.space = entity.id(),
             "Escape" => gui.escape = entity.id(),
             "Range" => gui.range = entity.id(),
+            "Odds" => gui.odds = entity.id(),
+            "Forecast" => gui.forecast = entity.id(),
+            "Seed" => gui.seed = entity.id(),
+            "Replay" => gui.replay = entity.id(),
             _ => {}
         }
     }
 }
 fn selected_unit(
-    units: Query<(Entity, &Health, &Movement, &Unit, &Attack)>,
+    units: Query<(Entity, &Health, &Movement, &Unit, &Attack, &Defense)>,
     mut texts: Query<(Entity, &mut Text)>,
     selected_res: Res<SelectedUnit>,
     gui: Res<SelectedUnitGUI>,
 ) {
     match selected_res.value {
         Some(selected) => match units.get(selected) {
-            Ok((_entity, health, movement, unit, attack)) => {
+            Ok((_entity, health, movement, unit, attack, defense)) => {
                 if let Some((_entity, mut text)) =
                     texts.iter_mut().find(|(e, _t)| gui.can_act == e.id())
                 {
-                    text.sections[0].value =
-                        format!("{}", if !unit.has_acted { "Can act" } else { "Acted" });
+                    text.sections[0].value = String::from(if unit.is_done() {
+                        "Done"
+                    } else if unit.has_moved {
+                        "Can attack"
+                    } else {
+                        "Can act"
+                    });
                 }
 
                 if let Some((_entity, mut text)) =
@@ -303,6 +379,19 @@ fn selected_unit(
                 {
                     text.sections[0].value = format!("{}", attack.range);
                 }
+                if let Some((_entity, mut text)) =
+                    texts.iter_mut().find(|(e, _t)| gui.odds == e.id())
+                {
+                    text.sections[0].value = format!(
+                        "Dmg {} {:?}\nAcc {}% Crit {}%\nDef {} Eva {}%",
+                        damage_range(attack.dmg, attack.max_dmg),
+                        attack.damage_type,
+                        attack.accuracy,
+                        attack.crit,
+                        defense.armor.defense,
+                        defense.evasion
+                    );
+                }
                 if let Some((_entity, mut text)) =
                     texts.iter_mut().find(|(e, _t)| gui.health == e.id())
                 {
@@ -321,6 +410,9 @@ fn selected_unit(
             {
                 text.sections[0].value = "".to_string();
             }
+            if let Some((_entity, mut text)) = texts.iter_mut().find(|(e, _t)| gui.odds == e.id()) {
+                text.sections[0].value = "".to_string();
+            }
             if let Some((_entity, mut text)) =
                 texts.iter_mut().find(|(e, _t)| gui.can_act == e.id())
             {
@@ -353,10 +445,9 @@ fn current_state(
     }
     if let Some((_entity, mut text)) = texts.iter_mut().find(|(e, _t)| gui.space == e.id()) {
         text.sections[0].value = match phase.current() {
-            TurnPhase::SelectUnit => String::from("Space: skip"),
-            TurnPhase::SelectMove => String::from("Space: wait"),
-            TurnPhase::SelectAttacker => String::from("Space: skip"),
-            TurnPhase::SelectTarget => String::from("Space: wait"),
+            TurnPhase::SelectUnit => String::from("Space: end turn"),
+            TurnPhase::SelectMove => String::from("Space: stay"),
+            TurnPhase::SelectTarget => String::from("Space: wait\nEnter: attack"),
             _ => String::from(""),
         }
     }
@@ -367,3 +458,93 @@ fn current_state(
         }
     }
 }
+/// Outcome of the attack on the hovered or picked target, before it is confirmed.
+fn forecast_text(
+    forecast: Res<AttackForecast>,
+    mut texts: Query<(Entity, &mut Text)>,
+    gui: Res<SelectedUnitGUI>,
+) {
+    if !forecast.is_changed() {
+        return;
+    }
+    if let Some((_entity, mut text)) = texts.iter_mut().find(|(e, _t)| gui.forecast == e.id()) {
+        text.sections[0].value = match &forecast.0 {
+            Some(forecast) => {
+                let mut lines = vec![
+                    format!(
+                        "You {} -> {}",
+                        forecast.attacker_health,
+                        forecast.attacker_after()
+                    ),
+                    format!(
+                        "Foe {} -> {}",
+                        forecast.target_health, forecast.attack.remaining
+                    ),
+                    format!(
+                        "Damage {}{}",
+                        damage_range(forecast.odds.min_damage, forecast.odds.max_damage),
+                        affinity_note(forecast.odds.affinity)
+                    ),
+                    format!("Hit {}% Crit {}%", forecast.odds.hit, forecast.odds.crit),
+                ];
+                if forecast.attack.killed {
+                    lines.push(String::from("Defeats target"));
+                }
+                match (forecast.counter, forecast.counter_odds) {
+                    (Some(counter), Some(odds)) => {
+                        lines.push(format!(
+                            "Counter {}{}",
+                            damage_range(odds.min_damage, odds.max_damage),
+                            affinity_note(odds.affinity)
+                        ));
+                        lines.push(format!("Hit {}% Crit {}%", odds.hit, odds.crit));
+                        if counter.killed {
+                            lines.push(String::from("Counter defeats you"));
+                        }
+                    }
+                    _ => lines.push(String::from("No counterattack")),
+                }
+                lines.push(String::from("Click again to attack"));
+                lines.join("\n")
+            }
+            None => String::from(""),
+        }
+    }
+}
+/// "5" for a fixed damage, "5-7" for a range.
+fn damage_range(min: i32, max: i32) -> String {
+    if max > min {
+        format!("{}-{}", min, max)
+    } else {
+        format!("{}", min)
+    }
+}
+fn affinity_note(affinity: Affinity) -> &'static str {
+    match affinity {
+        Affinity::Resistant => ", resisted",
+        Affinity::Neutral => "",
+        Affinity::Weak => ", weak spot",
+    }
+}
+/// Shows the battle seed so a bug report can name the battle to replay.
+fn seed_text(rng: Res<GameRng>, mut texts: Query<(Entity, &mut Text)>, gui: Res<SelectedUnitGUI>) {
+    if !rng.is_changed() {
+        return;
+    }
+    if let Some((_entity, mut text)) = texts.iter_mut().find(|(e, _t)| gui.seed == e.id()) {
+        text.sections[0].value = format!("Seed {}", rng.seed());
+    }
+}
+
+fn replay_text(
+    playback: Res<Playback>,
+    mut texts: Query<(Entity, &mut Text)>,
+    gui: Res<SelectedUnitGUI>,
+) {
+    if !playback.is_changed() {
+        return;
+    }
+    if let Some((_entity, mut text)) = texts.iter_mut().find(|(e, _t)| gui.replay == e.id()) {
+        text.sections[0].value = playback.status().unwrap_or_default();
+    }
+}
@@ -0,0 +1,151 @@
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::{
+    grid::{GridConfig, LevelEntity},
+    sim::Team,
+    units::{DamageDealt, Health, Unit},
+};
+
+pub struct HealthBarsPlugin;
+
+pub const BAR_WIDTH: f32 = 48.0;
+const BAR_HEIGHT: f32 = 6.0;
+/// Height of the bar above the centre of the unit.
+const BAR_OFFSET: f32 = 30.0;
+const NUMBER_LIFETIME: f32 = 1.0;
+/// Pixels per second a damage number rises.
+const NUMBER_RISE: f32 = 40.0;
+
+/// Filled part of the bar above a unit, a child of the unit.
+#[derive(Component)]
+pub struct HealthBar;
+
+/// Damage shown over a unit that was hit, it rises and fades out.
+#[derive(Component)]
+pub struct DamageNumber {
+    pub timer: Timer,
+}
+
+/// Share of the bar that is filled.
+pub fn health_fraction(health: &Health) -> f32 {
+    if health.max <= 0 {
+        return 0.0;
+    }
+    (health.value as f32 / health.max as f32).clamp(0.0, 1.0)
+}
+
+fn add_health_bars(mut commands: Commands, units: Query<(Entity, &Unit, &Health), Added<Health>>) {
+    for (entity, unit, health) in units.iter() {
+        let color = if unit.team == Team::PLAYER {
+            Color::GREEN
+        } else {
+            Color::RED
+        };
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(0.15, 0.15, 0.15),
+                    custom_size: Some(Vec2::new(BAR_WIDTH + 2.0, BAR_HEIGHT + 2.0)),
+                    ..default()
+                },
+                transform: Transform::from_translation(Vec3::new(0.0, BAR_OFFSET, 6.0)),
+                ..default()
+            });
+            parent
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        custom_size: Some(Vec2::new(
+                            BAR_WIDTH * health_fraction(health),
+                            BAR_HEIGHT,
+                        )),
+                        anchor: Anchor::CenterLeft,
+                        ..default()
+                    },
+                    transform: Transform::from_translation(Vec3::new(
+                        -BAR_WIDTH * 0.5,
+                        BAR_OFFSET,
+                        7.0,
+                    )),
+                    ..default()
+                })
+                .insert(HealthBar);
+        });
+    }
+}
+
+fn update_health_bars(
+    units: Query<(&Health, &Children), Changed<Health>>,
+    mut bars: Query<&mut Sprite, With<HealthBar>>,
+) {
+    for (health, children) in units.iter() {
+        for child in children.iter() {
+            if let Ok(mut sprite) = bars.get_mut(*child) {
+                sprite.custom_size =
+                    Some(Vec2::new(BAR_WIDTH * health_fraction(health), BAR_HEIGHT));
+            }
+        }
+    }
+}
+
+fn spawn_damage_numbers(
+    mut commands: Commands,
+    mut damage_dealt: EventReader<DamageDealt>,
+    asset_server: Res<AssetServer>,
+    grid_config: Res<GridConfig>,
+) {
+    for hit in damage_dealt.iter() {
+        let world = grid_config.grid_to_world(hit.tile);
+        let (label, color) = match (hit.hit, hit.crit) {
+            (false, _) => (String::from("Miss"), Color::GRAY),
+            (true, true) => (format!("-{}!", hit.damage), Color::YELLOW),
+            (true, false) => (format!("-{}", hit.damage), Color::ORANGE_RED),
+        };
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::from_section(
+                    label,
+                    TextStyle {
+                        font: asset_server.load("fonts/SourceCodePro.ttf"),
+                        font_size: 28.0,
+                        color,
+                    },
+                )
+                .with_alignment(TextAlignment::CENTER),
+                transform: Transform::from_translation(world.extend(20.0)),
+                ..default()
+            })
+            .insert(DamageNumber {
+                timer: Timer::from_seconds(NUMBER_LIFETIME, false),
+            })
+            .insert(LevelEntity);
+    }
+}
+
+fn float_damage_numbers(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut numbers: Query<(Entity, &mut DamageNumber, &mut Transform, &mut Text)>,
+) {
+    for (entity, mut number, mut transform, mut text) in numbers.iter_mut() {
+        number.timer.tick(time.delta());
+        if number.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        transform.translation.y += NUMBER_RISE * time.delta_seconds();
+        text.sections[0]
+            .style
+            .color
+            .set_a(1.0 - number.timer.percent());
+    }
+}
+
+impl Plugin for HealthBarsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(add_health_bars)
+            .add_system(update_health_bars)
+            .add_system(spawn_damage_numbers)
+            .add_system(float_damage_numbers);
+    }
+}
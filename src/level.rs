@@ -0,0 +1,304 @@
+use crate::content::{ContentError, ContentErrors};
+use crate::sim::{DamageType, Terrain};
+use crate::states::TurnPhase;
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadState, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const LEVEL_PATH: &str = "data/levels/001.json";
+
+pub struct LevelPlugin;
+
+#[derive(Serialize, Deserialize, Debug, TypeUuid)]
+#[uuid = "0b9c5e8e-54f4-4c36-9d4a-6a1b3f0c2d71"]
+pub struct UnitJson {
+    pub sprite: String,
+    pub movement: i32,
+    pub health: i32,
+    pub damage: i32,
+    /// Highest damage of a hit, hits roll between `damage` and this when it is set.
+    #[serde(default)]
+    pub max_damage: Option<i32>,
+    #[serde(default)]
+    pub damage_type: DamageType,
+    pub range: i32,
+    #[serde(default = "default_accuracy")]
+    pub accuracy: i32,
+    #[serde(default)]
+    pub evasion: i32,
+    #[serde(default)]
+    pub crit: i32,
+    #[serde(default)]
+    pub defense: i32,
+    /// Damage types the unit takes half damage from.
+    #[serde(default)]
+    pub resistances: Vec<DamageType>,
+    /// Damage types the unit takes half again as much damage from.
+    #[serde(default)]
+    pub weaknesses: Vec<DamageType>,
+    #[serde(default = "default_vision")]
+    pub vision: i32,
+    /// Whether the unit strikes back at attackers in its range that fail to defeat it.
+    #[serde(default = "default_counterattack")]
+    pub counterattack: bool,
+    /// Sprite shown while attacking, `<sprite>_attack.png` when left out.
+    #[serde(default)]
+    pub attack_sprite: Option<String>,
+}
+
+impl UnitJson {
+    pub fn max_damage(&self) -> i32 {
+        self.max_damage.unwrap_or(self.damage)
+    }
+
+    pub fn attack_sprite(&self) -> String {
+        if let Some(sprite) = &self.attack_sprite {
+            return sprite.clone();
+        }
+        match self.sprite.rsplit_once('.') {
+            Some((stem, extension)) => format!("{}_attack.{}", stem, extension),
+            None => format!("{}_attack", self.sprite),
+        }
+    }
+}
+
+/// Player squad, deployed onto the level's player spawns in order.
+#[derive(Serialize, Deserialize, Debug, TypeUuid)]
+#[uuid = "5d2f7a41-8c3e-4b6d-a0e9-1f4c7b2e9a36"]
+pub struct Roster {
+    pub units: Vec<UnitJson>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WaveUnit {
+    pub count: i32,
+    pub unit: String,
+    /// Spawn zone of the map to appear in, any ai spawn when left out.
+    #[serde(default)]
+    pub zone: Option<String>,
+}
+
+/// Fixed board authored in the level file.
+///
+/// `tiles` lists the rows from top to bottom, `#` marks an obstacle, `,` mud, `~` water,
+/// `=` road, `%` rubble and any other character grass. Spawns are grid coordinates,
+/// player units take them in order. Spawn zones are named groups of tiles that waves
+/// can send their units to.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MapLayout {
+    pub tiles: Vec<String>,
+    pub player_spawns: Vec<(i32, i32)>,
+    pub ai_spawns: Vec<(i32, i32)>,
+    #[serde(default)]
+    pub spawn_zones: BTreeMap<String, Vec<(i32, i32)>>,
+}
+
+impl MapLayout {
+    pub fn is_blocked(&self, x: i32, y: i32) -> bool {
+        self.tile_char(x, y) == Some('#')
+    }
+
+    pub fn terrain(&self, x: i32, y: i32) -> Terrain {
+        self.tile_char(x, y)
+            .map_or(Terrain::Grass, Terrain::from_char)
+    }
+
+    fn tile_char(&self, x: i32, y: i32) -> Option<char> {
+        let row = self.tiles.len() as i32 - 1 - y;
+        if row < 0 || x < 0 {
+            return None;
+        }
+        self.tiles
+            .get(row as usize)
+            .and_then(|r| r.chars().nth(x as usize))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, TypeUuid)]
+#[uuid = "9e4a1c37-2b6f-4d8e-b5c0-7a3d6f1e4b92"]
+pub struct Level {
+    #[serde(default = "default_size")]
+    pub width: i32,
+    #[serde(default = "default_size")]
+    pub height: i32,
+    #[serde(default)]
+    pub map: Option<MapLayout>,
+    #[serde(default = "default_roster")]
+    pub roster: String,
+    pub waves: Vec<Vec<WaveUnit>>,
+    /// Player actions that can be taken back per level, unlimited when left out.
+    #[serde(default)]
+    pub undo_limit: Option<usize>,
+    /// Fixed seed for the level's random rolls, a fresh one each start when left out.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+fn default_size() -> i32 {
+    9
+}
+
+fn default_vision() -> i32 {
+    4
+}
+
+fn default_counterattack() -> bool {
+    true
+}
+
+fn default_accuracy() -> i32 {
+    100
+}
+
+fn default_roster() -> String {
+    String::from("pirates")
+}
+
+pub fn roster_path(name: &str) -> String {
+    format!("data/rosters/{}.json", name)
+}
+
+pub fn enemy_path(name: &str) -> String {
+    format!("data/enemies/{}.json", name)
+}
+
+#[derive(Default)]
+pub struct LevelHandles {
+    pub level: Handle<Level>,
+    pub roster: Handle<Roster>,
+    pub enemies: HashMap<String, Handle<UnitJson>>,
+}
+
+impl LevelHandles {
+    pub fn enemy<'a>(&self, name: &str, units: &'a Assets<UnitJson>) -> Option<&'a UnitJson> {
+        self.enemies.get(name).and_then(|handle| units.get(handle))
+    }
+}
+
+/// Reads every `.json` under `assets/data`, the folder decides which type it becomes.
+#[derive(Default)]
+struct DataLoader;
+
+impl AssetLoader for DataLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let folder = load_context
+                .path()
+                .parent()
+                .and_then(|parent| parent.file_name())
+                .and_then(|name| name.to_str());
+            match folder {
+                Some("levels") => load_context
+                    .set_default_asset(LoadedAsset::new(serde_json::from_slice::<Level>(bytes)?)),
+                Some("rosters") => load_context
+                    .set_default_asset(LoadedAsset::new(serde_json::from_slice::<Roster>(bytes)?)),
+                Some("enemies") => {
+                    load_context.set_default_asset(LoadedAsset::new(serde_json::from_slice::<
+                        UnitJson,
+                    >(bytes)?))
+                }
+                _ => {
+                    return Err(bevy::asset::Error::msg(format!(
+                        "no data type for {}",
+                        load_context.path().display()
+                    )))
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["json"]
+    }
+}
+
+fn load_level_assets(mut handles: ResMut<LevelHandles>, asset_server: Res<AssetServer>) {
+    handles.level = asset_server.load(LEVEL_PATH);
+}
+
+fn load_level_dependencies(
+    mut events: EventReader<AssetEvent<Level>>,
+    mut handles: ResMut<LevelHandles>,
+    levels: Res<Assets<Level>>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                if let Some(level) = levels.get(handle) {
+                    handles.roster = asset_server.load(&roster_path(&level.roster));
+                    for wave_unit in level.waves.iter().flatten() {
+                        if !handles.enemies.contains_key(&wave_unit.unit) {
+                            let enemy = asset_server.load(&enemy_path(&wave_unit.unit));
+                            handles.enemies.insert(wave_unit.unit.clone(), enemy);
+                        }
+                    }
+                }
+            }
+            AssetEvent::Removed { .. } => {}
+        }
+    }
+}
+
+fn wait_for_assets(
+    handles: Res<LevelHandles>,
+    levels: Res<Assets<Level>>,
+    asset_server: Res<AssetServer>,
+    mut content_errors: ResMut<ContentErrors>,
+    mut phase: ResMut<State<TurnPhase>>,
+) {
+    if !content_errors.0.is_empty() {
+        phase.set(TurnPhase::ContentError).unwrap();
+        return;
+    }
+    if asset_server.get_load_state(&handles.level) == LoadState::Failed {
+        content_errors.0.push(ContentError {
+            path: LEVEL_PATH.to_string(),
+            field: String::from("."),
+            message: String::from("failed to load, see the log"),
+        });
+        return;
+    }
+    if levels.get(&handles.level).is_none() {
+        return;
+    }
+    let ids = handles
+        .enemies
+        .values()
+        .map(|handle| handle.id)
+        .chain([handles.roster.id]);
+    match asset_server.get_group_load_state(ids) {
+        LoadState::Loaded => phase.set(TurnPhase::LoadLevel).unwrap(),
+        LoadState::Failed => content_errors.0.push(ContentError {
+            path: LEVEL_PATH.to_string(),
+            field: String::from("."),
+            message: String::from("a roster or enemy failed to load, see the log"),
+        }),
+        _ => {}
+    }
+}
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Level>()
+            .add_asset::<Roster>()
+            .add_asset::<UnitJson>()
+            .init_asset_loader::<DataLoader>()
+            .init_resource::<LevelHandles>()
+            .add_startup_system(load_level_assets)
+            .add_system(load_level_dependencies)
+            .add_system_set(
+                SystemSet::on_update(TurnPhase::LoadAssets).with_system(wait_for_assets),
+            );
+    }
+}
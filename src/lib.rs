@@ -0,0 +1,20 @@
+pub mod ai_units;
+pub mod attacks;
+pub mod camera;
+pub mod cli;
+pub mod content;
+pub mod fog;
+pub mod game_over;
+pub mod grid;
+pub mod gui;
+pub mod health_bars;
+pub mod level;
+pub mod pathfinding;
+pub mod player_units;
+pub mod replay;
+pub mod rng;
+pub mod save;
+pub mod sim;
+pub mod states;
+pub mod undo;
+pub mod units;
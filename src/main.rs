@@ -1,30 +1,72 @@
-use bevy::prelude::*;
+use bevy::{asset::AssetServerSettings, prelude::*};
+use std::path::Path;
 
-mod ai_units;
-mod camera;
-mod grid;
-mod gui;
-mod pathfinding;
-mod player_units;
-mod states;
-mod units;
-
-use crate::{
-    ai_units::AiUnitsPlugin, camera::CameraPlugin, grid::GridPlugin, gui::GuiPlugin,
-    pathfinding::PathfindingPlugin, player_units::PlayerUnitsPlugin, states::TurnPhase,
+use tbt::{
+    ai_units::AiUnitsPlugin,
+    attacks::AttacksPlugin,
+    camera::CameraPlugin,
+    cli::Args,
+    content::ContentPlugin,
+    fog::FogPlugin,
+    game_over::GameOverPlugin,
+    grid::GridPlugin,
+    gui::GuiPlugin,
+    health_bars::HealthBarsPlugin,
+    level::LevelPlugin,
+    pathfinding::PathfindingPlugin,
+    player_units::PlayerUnitsPlugin,
+    replay::{read_replay, Playback, ReplayPlugin},
+    rng::{RngPlugin, SeedArg},
+    save::SavePlugin,
+    states::TurnPhase,
+    undo::UndoPlugin,
     units::UnitsPlugin,
 };
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
+    let mut app = App::new();
+    // lets edited data files reload while the game runs
+    app.insert_resource(AssetServerSettings {
+        watch_for_changes: true,
+        ..default()
+    })
+    .add_plugins(DefaultPlugins);
+
+    // read once logging is set up, so complaints about the arguments are shown
+    let args = Args::parse(std::env::args().skip(1));
+    let replay = args
+        .replay
+        .and_then(|path| match read_replay(Path::new(&path)) {
+            Ok(replay) => Some(replay),
+            Err(err) => {
+                error!(
+                    "could not read replay {}, starting a new battle: {}",
+                    path, err
+                );
+                None
+            }
+        });
+    let seed = replay.as_ref().map(|replay| replay.seed).or(args.seed);
+
+    app.add_plugin(ContentPlugin)
+        .add_plugin(LevelPlugin)
+        .add_plugin(RngPlugin)
+        .add_plugin(ReplayPlugin)
+        .insert_resource(SeedArg(seed))
+        .insert_resource(replay.map(Playback::new).unwrap_or_default())
         .add_plugin(CameraPlugin)
         .add_plugin(GridPlugin)
+        .add_plugin(FogPlugin)
         .add_plugin(UnitsPlugin)
         .add_plugin(PlayerUnitsPlugin)
         .add_plugin(AiUnitsPlugin)
+        .add_plugin(AttacksPlugin)
         .add_plugin(PathfindingPlugin)
         .add_plugin(GuiPlugin)
-        .add_state(TurnPhase::SelectUnit)
+        .add_plugin(HealthBarsPlugin)
+        .add_plugin(GameOverPlugin)
+        .add_plugin(SavePlugin)
+        .add_plugin(UndoPlugin)
+        .add_state(TurnPhase::LoadAssets)
         .run();
 }
@@ -1,20 +1,32 @@
-use crate::ai_units::Ai;
-use crate::camera::MainCamera;
+use crate::attacks::{AttackAnimation, PendingAttack};
 use crate::grid::{
-    clear_highlighted_tiles_func, BlockedTiles, GridConfig, GridPosition, SelectedPath,
+    clear_highlighted_tiles_func, GridConfig, GridPosition, LevelEntity, SelectedPath,
     SelectedTile, Tile,
 };
-use crate::pathfinding::calculate_a_star_path;
+use crate::level::{LevelHandles, Roster};
+use crate::replay::{BattleCommand, Playback};
+use crate::rng::GameRng;
+use crate::sim::{Battle, Board, Coord, Forecast, Team};
 use crate::states::TurnPhase;
-use crate::units::{ActiveUnit, Attack, Health, Movement, SelectedUnit, Team, Unit};
+use crate::units::{
+    apply_unit_stats, ActiveUnit, Attack, AttackTarget, BattleUnits, Defense, Health, Movement,
+    SelectedUnit, Spawners, TileClick, TileHover, Unit, UnitSprite, Vision,
+};
 use bevy::prelude::*;
-use bevy::render::camera::RenderTarget;
 
 pub struct PlayerUnitsPlugin;
 
 #[derive(Component, Debug)]
 pub struct Player;
 
+/// Outcome of the attack being considered in `SelectTarget`, for the gui.
+#[derive(Default, Debug)]
+pub struct AttackForecast(pub Option<Forecast>);
+
+/// Index of the roster entry a player unit was spawned from.
+#[derive(Component, Debug)]
+pub struct RosterSlot(pub usize);
+
 fn setup_active(mut commands: Commands) {
     commands.insert_resource(ActiveUnit { ..default() });
 }
@@ -26,34 +38,30 @@ fn move_active_unit(
     mut player_units: Query<(Entity, &mut Transform, &mut GridPosition, &mut Unit), With<Player>>,
     mut phase: ResMut<State<TurnPhase>>,
     grid_config: Res<GridConfig>,
+    playback: Res<Playback>,
 ) {
     match active_res.value {
         Some(active) => match player_units.get_mut(active) {
             Ok((_e, mut transform, mut grid, mut player)) => {
                 let mut should_pop = false;
                 if let Some(next_tile) = selected_path.tiles.last() {
-                    let direction = Vec3::new(
-                        next_tile.0 as f32 * grid_config.tile_size - grid_config.offset(),
-                        next_tile.1 as f32 * grid_config.tile_size - grid_config.offset(),
-                        0.0,
-                    ) - transform.translation;
+                    let target = grid_config
+                        .grid_to_world(*next_tile)
+                        .extend(transform.translation.z);
+                    let direction = target - transform.translation;
 
                     if direction.length() > 1.0 {
                         transform.translation +=
-                            direction.normalize() * time.delta_seconds() * 100.0;
+                            direction.normalize() * time.delta_seconds() * 100.0 * playback.speed();
                     } else {
-                        transform.translation = Vec3::new(
-                            next_tile.0 as f32 * grid_config.tile_size - grid_config.offset(),
-                            next_tile.1 as f32 * grid_config.tile_size - grid_config.offset(),
-                            0.0,
-                        );
+                        transform.translation = target;
                         grid.x = next_tile.0;
                         grid.y = next_tile.1;
                         should_pop = true;
                     }
                 } else {
-                    player.has_acted = true;
-                    phase.set(TurnPhase::SelectUnit).unwrap();
+                    player.has_moved = true;
+                    phase.set(TurnPhase::SelectTarget).unwrap();
                 }
                 if should_pop {
                     selected_path.tiles.pop();
@@ -65,47 +73,54 @@ fn move_active_unit(
     }
 }
 
-fn spawn_unit(
-    x: f32,
-    y: f32,
+pub fn spawn_unit(
+    world: Vec2,
     i: i32,
     grid: (i32, i32),
     commands: &mut Commands,
     asset_server: &Res<AssetServer>,
     sprite_path: &str,
+    attack_sprite_path: &str,
     movement: i32,
     health: i32,
-    dmg: i32,
-    range: i32,
+    attack: Attack,
+    defense: Defense,
+    vision: i32,
 ) -> Entity {
     commands
         .spawn()
         .insert_bundle(SpatialBundle {
-            transform: Transform::from_translation(Vec3::new(x, y, 1.0)),
+            transform: Transform::from_translation(world.extend(1.0)),
             ..default()
         })
         .with_children(|parent| {
-            parent.spawn_bundle(SpriteBundle {
-                texture: asset_server.load(sprite_path),
-                transform: Transform::from_translation(Vec3::new(0.0, 0.0, 5.0)),
-                ..default()
-            });
+            parent
+                .spawn_bundle(SpriteBundle {
+                    texture: asset_server.load(sprite_path),
+                    transform: Transform::from_translation(Vec3::new(0.0, 0.0, 5.0)),
+                    ..default()
+                })
+                .insert(UnitSprite {
+                    idle: asset_server.load(sprite_path),
+                    attack: asset_server.load(attack_sprite_path),
+                });
         })
         .insert(Unit {
-            has_acted: false,
+            has_moved: false,
+            has_attacked: false,
             team: Team::PLAYER,
         })
         .insert(Player)
+        .insert(RosterSlot(i as usize))
         .insert(Name::new(format!("Player Unit {}", i)))
         .insert(Movement { distance: movement })
         .insert(Health {
             max: health,
             value: health,
         })
-        .insert(Attack {
-            dmg: dmg,
-            range: range,
-        })
+        .insert(attack)
+        .insert(defense)
+        .insert(Vision { radius: vision })
         .insert(GridPosition {
             x: grid.0,
             y: grid.1,
@@ -113,241 +128,258 @@ fn spawn_unit(
         .id()
 }
 
-fn make_units(
+pub fn make_units(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     grid_config: Res<GridConfig>,
+    spawners: Res<Spawners>,
+    handles: Res<LevelHandles>,
+    rosters: Res<Assets<Roster>>,
 ) {
-    let mut units = Vec::new();
-    let sprites = [
-        "sprites/pirate_1.png",
-        "sprites/pirate_2.png",
-        "sprites/pirate_3.png",
-    ];
-    let movements = [1, 5, 3];
-    let healths = [20, 15, 10];
-    let dmgs = [7, 3, 5];
-    let ranges = [1, 1, 4];
-    let positions = [
-        (
-            grid_config.rows_cols / 2 as i32,
-            grid_config.rows_cols / 2 as i32,
-        ),
-        (
-            grid_config.rows_cols / 2 as i32 - 1,
-            grid_config.rows_cols / 2 as i32,
-        ),
-        (
-            grid_config.rows_cols / 2 as i32 + 1,
-            grid_config.rows_cols / 2 as i32,
-        ),
-    ];
+    let roster = rosters
+        .get(&handles.roster)
+        .expect("roster should be loaded before LoadLevel");
 
-    for i in 0..sprites.len() as i32 {
-        let x = (positions[i as usize].0 as f32 * grid_config.tile_size) - grid_config.offset();
-        let y = (positions[i as usize].1 as f32 * grid_config.tile_size) - grid_config.offset();
+    let mut units = Vec::new();
+    for (i, (unit, &grid)) in roster
+        .units
+        .iter()
+        .zip(spawners.player_locations.iter())
+        .enumerate()
+    {
         let unit = spawn_unit(
-            x,
-            y,
-            i,
-            positions[i as usize],
+            grid_config.grid_to_world(grid),
+            i as i32,
+            grid,
             &mut commands,
             &asset_server,
-            sprites[i as usize],
-            movements[i as usize],
-            healths[i as usize],
-            dmgs[i as usize],
-            ranges[i as usize],
+            &format!("sprites/{}", unit.sprite),
+            &format!("sprites/{}", unit.attack_sprite()),
+            unit.movement,
+            unit.health,
+            Attack::from(unit),
+            Defense::from(unit),
+            unit.vision,
         );
         units.push(unit);
     }
     commands
         .spawn()
         .insert(Name::new("Player Units"))
+        .insert(LevelEntity)
         .insert_bundle(SpatialBundle::default())
         .push_children(&units);
 }
 
-fn get_mouse_position(
-    windows: Res<Windows>,
-    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-) -> Vec2 {
-    // assuming there is exactly one main camera entity, so query::single() is OK
-    let (camera, camera_transform) = q_camera.single();
-
-    // get the window that the camera is displaying to (or the primary window)
-    let wnd = if let RenderTarget::Window(id) = camera.target {
-        windows.get(id).unwrap()
-    } else {
-        windows.get_primary().unwrap()
-    };
-
-    // check if the cursor is inside the window and get its position
-    if let Some(screen_pos) = wnd.cursor_position() {
-        // get the size of the window
-        let window_size = Vec2::new(wnd.width() as f32, wnd.height() as f32);
-        // convert screen position [0..resolution] to ndc [-1..1] (gpu coordinates)
-        let ndc = (screen_pos / window_size) * 2.0 - Vec2::ONE;
-        // matrix for undoing the projection and camera transform
-        let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
-        // use it to convert ndc to world-space coordinates
-        let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
-        // reduce it to a 2D value
-        return world_pos.truncate();
-    }
-    return Vec2::ZERO;
-}
-
 fn select_move(
-    mut mouse_input: ResMut<Input<MouseButton>>,
-    windows: Res<Windows>,
-    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-    tiles: Query<(&Tile, &GridPosition, &mut Transform)>,
+    mut click: ResMut<TileClick>,
+    tiles: Query<(&Tile, &GridPosition)>,
     player_unit_grids: Query<(Entity, &GridPosition), With<Player>>,
     movements: Query<(Entity, &Movement)>,
     active_res: Res<ActiveUnit>,
     mut selected_tile: ResMut<SelectedTile>,
     mut phase: ResMut<State<TurnPhase>>,
-    blocked: Res<BlockedTiles>,
+    board: Res<Board>,
+    mut decisions: EventWriter<BattleCommand>,
 ) {
-    if mouse_input.just_pressed(MouseButton::Left) {
+    if click.tile.is_some() {
         match active_res.value {
-            Some(active) => {
-                match movements.get(active) {
-                    Ok((_e, active_movement)) => {
-                        match player_unit_grids.get(active) {
-                            Ok((_e, active_grid)) => {
-                                let mouse_pos = get_mouse_position(windows, q_camera);
-                                //get closest
-                                let min_dist = 32.0;
-                                // let mut selection: Option<&Label> = None;
-                                let selection =
-                                    tiles.into_iter().find(|(_tile, _grid, transform)| {
-                                        mouse_pos.distance(Vec2::new(
-                                            transform.translation.x,
-                                            transform.translation.y,
-                                        )) <= min_dist
+            Some(active) => match movements.get(active) {
+                Ok((_e, active_movement)) => match player_unit_grids.get(active) {
+                    Ok((_e, active_grid)) => {
+                        let selection = tiles
+                            .into_iter()
+                            .find(|(_tile, grid)| click.tile == Some((grid.x, grid.y)));
+                        match selection {
+                            Some((_tile, grid)) => {
+                                let reachable = board.reachable_tiles(
+                                    (active_grid.x, active_grid.y),
+                                    active_movement.distance,
+                                );
+                                let dist = reachable.cost((grid.x, grid.y)).unwrap_or(0);
+                                if dist >= 1 {
+                                    selected_tile.x = grid.x;
+                                    selected_tile.y = grid.y;
+                                    decisions.send(BattleCommand::Move {
+                                        team: Team::PLAYER,
+                                        to: (grid.x, grid.y),
                                     });
-                                match selection {
-                                    Some((_tile, grid, _transform)) => {
-                                        let dist = calculate_a_star_path(
-                                            (active_grid.x, active_grid.y),
-                                            (grid.x, grid.y),
-                                            &blocked,
-                                        )
-                                        .len()
-                                            as i32;
-                                        if dist >= 1 && dist <= active_movement.distance {
-                                            selected_tile.x = grid.x;
-                                            selected_tile.y = grid.y;
-                                            phase.set(TurnPhase::DoMove).unwrap();
-                                            mouse_input.reset(MouseButton::Left);
-                                        }
-                                    }
-                                    None => {}
+                                    phase.set(TurnPhase::DoMove).unwrap();
+                                    click.tile = None;
                                 }
                             }
-                            Err(_) => {}
+                            None => {}
                         }
                     }
                     Err(_) => {}
-                }
-            }
+                },
+                Err(_) => {}
+            },
             None => {}
         }
     }
 }
 
+/// Enemy on `tile` the active unit can attack from where it stands.
+fn target_at(battle: &Battle, attacker: usize, tile: Option<Coord>) -> Option<usize> {
+    tile.and_then(|tile| battle.unit_at(tile))
+        .filter(|target| battle.can_attack(attacker, *target))
+}
+
+/// A click picks an enemy in range, clicking it again or pressing Enter attacks it.
 fn select_target(
-    mut mouse_input: ResMut<Input<MouseButton>>,
-    windows: Res<Windows>,
-    mut ai_units: Query<(Entity, &GridPosition, &Transform, &mut Health), With<Ai>>,
-    mut player_units: Query<(Entity, &mut Unit, &GridPosition, &Attack), With<Player>>,
-    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-    active_res: ResMut<ActiveUnit>,
+    mut click: ResMut<TileClick>,
+    key_input: Res<Input<KeyCode>>,
+    mut target: ResMut<AttackTarget>,
+    mut units: BattleUnits,
+    active_res: Res<ActiveUnit>,
     mut phase: ResMut<State<TurnPhase>>,
-    mut commands: Commands,
+    mut pending: ResMut<PendingAttack>,
+    mut decisions: EventWriter<BattleCommand>,
+    mut rng: ResMut<GameRng>,
 ) {
-    if mouse_input.just_pressed(MouseButton::Left) {
-        match active_res.value {
-            Some(active) => {
-                let mouse_pos = get_mouse_position(windows, q_camera);
-                //get closest
-                let min_dist = 32.0;
-                match player_units.get_mut(active) {
-                    Ok((_active, mut active_player, active_grid, active_attack)) => {
-                        let selection =
-                            ai_units.iter_mut().find(|(_e, grid, transform, _health)| {
-                                let dist = std::cmp::max(
-                                    i32::abs(grid.x - active_grid.x),
-                                    i32::abs(grid.y - active_grid.y),
-                                );
-                                dist > 0
-                                    && dist <= active_attack.range
-                                    && mouse_pos.distance(Vec2::new(
-                                        transform.translation.x,
-                                        transform.translation.y,
-                                    )) <= min_dist
+    let (mut battle, entities) = units.battle(Team::PLAYER);
+    match active_res.value {
+        Some(active) => match entities.iter().position(|entity| *entity == active) {
+            Some(attacker) => {
+                let mut confirmed = false;
+                if target_at(&battle, attacker, click.tile).is_some() {
+                    confirmed = target.tile == click.tile;
+                    target.tile = click.tile;
+                    click.tile = None;
+                }
+                confirmed |= key_input.just_pressed(KeyCode::Return);
+                if !confirmed {
+                    return;
+                }
+                match target_at(&battle, attacker, target.tile) {
+                    Some(defender) => match battle.attack(attacker, defender, &mut *rng) {
+                        Ok(exchange) => {
+                            decisions.send(BattleCommand::Attack {
+                                team: Team::PLAYER,
+                                target: battle.units[defender].pos,
                             });
-                        match selection {
-                            Some((e, _g, _t, mut target_health)) => {
-                                target_health.value -= active_attack.dmg;
-                                if target_health.value <= 0 {
-                                    commands.entity(e).despawn_recursive();
-                                }
-                                active_player.has_acted = true;
-                                phase.set(TurnPhase::SelectAttacker).unwrap();
-                                mouse_input.clear();
-                            }
-                            None => {}
+                            units.apply(&battle, &entities);
+                            pending.0 = Some(AttackAnimation::new(
+                                &battle, &entities, attacker, defender, exchange,
+                            ));
+                            target.tile = None;
+                            phase.set(TurnPhase::DoAttack).unwrap();
                         }
-                    }
-                    Err(_) => todo!(),
+                        Err(err) => warn!("the attack is not allowed: {:?}", err),
+                    },
+                    None => {}
                 }
             }
-            None => {}
-        }
+            None => drop_stale_unit(&mut phase),
+        },
+        None => {}
     }
 }
-fn check_player_has_moved(
-    mut player_units: Query<&mut Unit, With<Player>>,
-    mut phase: ResMut<State<TurnPhase>>,
+
+/// Previews the attack on the chosen enemy, or on the one under the cursor.
+fn update_forecast(
+    hover: Res<TileHover>,
+    target: Res<AttackTarget>,
+    mut forecast: ResMut<AttackForecast>,
+    units: BattleUnits,
+    active_res: Res<ActiveUnit>,
 ) {
-    let mut still_to_act = false;
-    for unit in player_units.iter() {
-        if unit.has_acted == false {
-            still_to_act = true;
-        }
+    let (battle, entities) = units.battle(Team::PLAYER);
+    let tile = target.tile.or(hover.tile);
+    let next = active_res
+        .value
+        .and_then(|active| entities.iter().position(|entity| *entity == active))
+        .and_then(|attacker| {
+            target_at(&battle, attacker, tile).map(|defender| battle.forecast(attacker, defender))
+        });
+    if forecast.0 != next {
+        forecast.0 = next;
     }
-    if !still_to_act {
-        for mut unit in player_units.iter_mut() {
-            unit.has_acted = false;
-        }
-        phase.set(TurnPhase::SelectAttacker).unwrap();
+}
+
+fn clear_forecast(mut target: ResMut<AttackTarget>, mut forecast: ResMut<AttackForecast>) {
+    target.tile = None;
+    forecast.0 = None;
+}
+
+fn check_player_turn_done(mut units: BattleUnits, mut phase: ResMut<State<TurnPhase>>) {
+    let (mut battle, entities) = units.battle(Team::PLAYER);
+    // a side without units has nothing left to do, the defeat check takes over
+    if battle.winner() != Some(Team::AI)
+        && battle.turn_over()
+        && phase.set(TurnPhase::AISelectUnit).is_ok()
+    {
+        battle.end_turn();
+        units.apply(&battle, &entities);
     }
 }
 
-fn check_player_has_attacked(
-    mut player_units: Query<&mut Unit, With<Player>>,
-    mut phase: ResMut<State<TurnPhase>>,
-) {
-    let mut still_to_act = false;
-    for unit in player_units.iter() {
-        if unit.has_acted == false {
-            still_to_act = true;
-        }
+fn check_defeat(units: BattleUnits, mut phase: ResMut<State<TurnPhase>>) {
+    // a defeated unit fades out before the end screen shows
+    let playing = !matches!(
+        phase.current(),
+        TurnPhase::LoadAssets
+            | TurnPhase::LoadLevel
+            | TurnPhase::LoadSave
+            | TurnPhase::DoAttack
+            | TurnPhase::AIDoAttack
+            | TurnPhase::Victory
+            | TurnPhase::Defeat
+            | TurnPhase::ContentError
+    );
+    if playing && units.battle(Team::PLAYER).0.winner() == Some(Team::AI) {
+        phase.set(TurnPhase::Defeat).unwrap();
     }
-    if !still_to_act {
-        for mut unit in player_units.iter_mut() {
-            unit.has_acted = false;
+}
+
+fn reload_roster_stats(
+    mut events: EventReader<AssetEvent<Roster>>,
+    rosters: Res<Assets<Roster>>,
+    mut player_units: Query<
+        (
+            &RosterSlot,
+            &mut Movement,
+            &mut Health,
+            &mut Attack,
+            &mut Defense,
+            &mut Vision,
+        ),
+        With<Player>,
+    >,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            if let Some(roster) = rosters.get(handle) {
+                for (slot, mut movement, mut health, mut attack, mut defense, mut vision) in
+                    player_units.iter_mut()
+                {
+                    if let Some(unit) = roster.units.get(slot.0) {
+                        apply_unit_stats(
+                            unit,
+                            &mut movement,
+                            &mut health,
+                            &mut attack,
+                            &mut defense,
+                            &mut vision,
+                        );
+                    }
+                }
+            }
         }
-        phase.set(TurnPhase::AISelectUnit).unwrap();
     }
 }
 
 fn clear_active_unit(mut active: ResMut<ActiveUnit>) {
     active.value = None;
 }
+
+// the active unit can be gone after a load, an undo or a deadly counter
+fn drop_stale_unit(phase: &mut State<TurnPhase>) {
+    warn!("the active unit is gone, selecting a new one");
+    let _ = phase.set(TurnPhase::SelectUnit);
+}
+
 fn handle_keys(
     mut active_res: ResMut<ActiveUnit>,
     mut selected_res: ResMut<SelectedUnit>,
@@ -355,17 +387,15 @@ fn handle_keys(
     mut key_input: ResMut<Input<KeyCode>>,
     mut player_units: Query<(Entity, &mut Unit), With<Player>>,
     mut tiles: Query<&mut Sprite, With<Tile>>,
+    mut decisions: EventWriter<BattleCommand>,
 ) {
     if key_input.just_pressed(KeyCode::Escape) {
         match phase.current() {
-            TurnPhase::SelectMove => {
+            TurnPhase::SelectMove | TurnPhase::SelectTarget => {
+                decisions.send(BattleCommand::Back);
                 phase.set(TurnPhase::SelectUnit).unwrap();
                 active_res.value = None;
             }
-            TurnPhase::SelectTarget => {
-                phase.set(TurnPhase::SelectAttacker).unwrap();
-                active_res.value = None;
-            }
             _ => {}
         }
         clear_highlighted_tiles_func(&mut tiles);
@@ -377,28 +407,34 @@ fn handle_keys(
             TurnPhase::SelectMove => match active_res.value {
                 Some(active) => match player_units.get_mut(active) {
                     Ok((_entity, mut unit)) => {
-                        unit.has_acted = true;
-                        active_res.value = None;
-                        phase.set(TurnPhase::SelectUnit).unwrap();
+                        decisions.send(BattleCommand::Stay);
+                        unit.has_moved = true;
+                        phase.set(TurnPhase::SelectTarget).unwrap();
                     }
-                    Err(_) => {}
+                    Err(_) => drop_stale_unit(&mut phase),
                 },
-                None => todo!(),
+                None => drop_stale_unit(&mut phase),
             },
             TurnPhase::SelectTarget => match active_res.value {
                 Some(active) => match player_units.get_mut(active) {
                     Ok((_entity, mut unit)) => {
-                        unit.has_acted = true;
+                        decisions.send(BattleCommand::Wait { team: Team::PLAYER });
+                        unit.has_moved = true;
+                        unit.has_attacked = true;
                         active_res.value = None;
-                        phase.set(TurnPhase::SelectAttacker).unwrap();
+                        phase.set(TurnPhase::SelectUnit).unwrap();
                     }
-                    Err(_) => {}
+                    Err(_) => drop_stale_unit(&mut phase),
                 },
-                None => todo!(),
+                None => drop_stale_unit(&mut phase),
             },
-            TurnPhase::SelectUnit => phase.set(TurnPhase::SelectAttacker).unwrap(),
-            TurnPhase::DoMove => {}
-            TurnPhase::SelectAttacker => phase.set(TurnPhase::AISelectUnit).unwrap(),
+            TurnPhase::SelectUnit => {
+                decisions.send(BattleCommand::EndTurn);
+                for (_entity, mut unit) in player_units.iter_mut() {
+                    unit.has_moved = true;
+                    unit.has_attacked = true;
+                }
+            }
             _ => {}
         }
         key_input.clear();
@@ -407,23 +443,31 @@ fn handle_keys(
 
 impl Plugin for PlayerUnitsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(make_units)
+        app.init_resource::<AttackForecast>()
             .add_startup_system(setup_active)
-            .add_system_set(SystemSet::on_update(TurnPhase::DoMove).with_system(move_active_unit))
-            .add_system_set(SystemSet::on_update(TurnPhase::SelectMove).with_system(select_move))
             .add_system_set(
-                SystemSet::on_update(TurnPhase::SelectUnit).with_system(check_player_has_moved), // .with_system(select_unit.after(check_player_has_moved)),
+                SystemSet::on_enter(TurnPhase::LoadLevel)
+                    .with_system(make_units.after(crate::grid::create_level)),
             )
+            .add_system_set(SystemSet::on_update(TurnPhase::DoMove).with_system(move_active_unit))
+            .add_system_set(SystemSet::on_update(TurnPhase::SelectMove).with_system(select_move))
             .add_system_set(
-                SystemSet::on_update(TurnPhase::SelectAttacker)
-                    .with_system(check_player_has_attacked),
+                SystemSet::on_update(TurnPhase::SelectUnit).with_system(
+                    check_player_turn_done.after(crate::ai_units::check_remaining_units),
+                ),
             )
             .add_system_set(
-                SystemSet::on_update(TurnPhase::SelectTarget).with_system(select_target),
+                SystemSet::on_update(TurnPhase::SelectTarget)
+                    .with_system(select_target)
+                    .with_system(update_forecast.after(select_target)),
             )
+            .add_system_set(SystemSet::on_exit(TurnPhase::SelectTarget).with_system(clear_forecast))
             .add_system_set(
                 SystemSet::on_enter(TurnPhase::SelectUnit).with_system(clear_active_unit),
             )
-            .add_system(handle_keys);
+            .add_system(handle_keys)
+            .add_system(reload_roster_stats)
+            // after every phase change of the frame has been applied
+            .add_system_to_stage(CoreStage::PostUpdate, check_defeat);
     }
 }
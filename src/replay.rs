@@ -0,0 +1,350 @@
+use crate::{
+    level::LEVEL_PATH,
+    rng::GameRng,
+    save::UserDir,
+    sim::{Coord, Team},
+    states::TurnPhase,
+    units::TileClick,
+};
+use bevy::{input::InputSystem, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+
+pub const REPLAY_PATH: &str = "replays/latest.json";
+
+const DECISION_DELAY: f32 = 0.5;
+const FAST_FORWARD: f32 = 4.0;
+
+/// Keys that decide or reload the battle, taken away from the player during a replay.
+const BATTLE_KEYS: [KeyCode; 6] = [
+    KeyCode::Space,
+    KeyCode::Escape,
+    KeyCode::Return,
+    KeyCode::U,
+    KeyCode::F9,
+    KeyCode::F10,
+];
+
+pub struct ReplayPlugin;
+
+/// A decision taken by the player or the ai, units are named by the tile they stand on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BattleCommand {
+    Select { team: Team, unit: Coord },
+    Move { team: Team, to: Coord },
+    Attack { team: Team, target: Coord },
+    Wait { team: Team },
+    Stay,
+    Back,
+    EndTurn,
+    Undo,
+}
+
+impl BattleCommand {
+    fn team(&self) -> Team {
+        match self {
+            BattleCommand::Select { team, .. }
+            | BattleCommand::Move { team, .. }
+            | BattleCommand::Attack { team, .. }
+            | BattleCommand::Wait { team } => *team,
+            BattleCommand::Stay
+            | BattleCommand::Back
+            | BattleCommand::EndTurn
+            | BattleCommand::Undo => Team::PLAYER,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Replay {
+    pub level: String,
+    pub seed: u64,
+    pub commands: Vec<BattleCommand>,
+}
+
+#[derive(Default)]
+pub struct CommandLog {
+    pub replay: Replay,
+    recording: bool,
+}
+
+impl CommandLog {
+    /// Stops recording until the next level start, for when the battle jumps to a loaded save.
+    pub fn interrupt(&mut self) {
+        if self.recording {
+            warn!("the battle was loaded from a save, it is no longer recorded");
+        }
+        self.recording = false;
+    }
+}
+
+#[derive(Default)]
+pub struct Playback {
+    replay: Option<Replay>,
+    cursor: usize,
+    paused: bool,
+    steps: usize,
+    fast: bool,
+    since_decision: f32,
+    /// Command fed in on the previous frame, it should have been taken by now.
+    fed: Option<usize>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Playback {
+            replay: Some(replay),
+            ..default()
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    pub fn ready(&self) -> bool {
+        let delay = if self.fast {
+            DECISION_DELAY / FAST_FORWARD
+        } else {
+            DECISION_DELAY
+        };
+        !self.is_playing() || ((!self.paused || self.steps > 0) && self.since_decision >= delay)
+    }
+
+    /// Takes the next decision if it is `ready`, used by the ai to keep pace with the replay.
+    pub fn decide(&mut self) -> bool {
+        if !self.ready() {
+            return false;
+        }
+        if self.is_playing() {
+            self.since_decision = 0.0;
+            self.steps = self.steps.saturating_sub(1);
+        }
+        true
+    }
+
+    pub fn speed(&self) -> f32 {
+        if self.is_playing() && self.fast {
+            FAST_FORWARD
+        } else {
+            1.0
+        }
+    }
+
+    pub fn status(&self) -> Option<String> {
+        let replay = self.replay.as_ref()?;
+        let state = if self.paused {
+            "paused"
+        } else if self.fast {
+            "fast"
+        } else {
+            "playing"
+        };
+        Some(format!(
+            "Replay {}/{} {}",
+            self.cursor,
+            replay.commands.len(),
+            state
+        ))
+    }
+
+    fn next(&self) -> Option<BattleCommand> {
+        self.replay
+            .as_ref()
+            .and_then(|replay| replay.commands.get(self.cursor))
+            .copied()
+    }
+
+    fn stop(&mut self) {
+        self.replay = None;
+    }
+}
+
+pub fn write_replay(path: &Path, replay: &Replay) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = fs::File::create(path)?;
+    serde_json::to_writer_pretty(file, replay)?;
+    Ok(())
+}
+
+pub fn read_replay(path: &Path) -> io::Result<Replay> {
+    let file = fs::File::open(path)?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+fn start_recording(mut log: ResMut<CommandLog>, rng: Res<GameRng>, playback: Res<Playback>) {
+    if let Some(replay) = &playback.replay {
+        if replay.level != LEVEL_PATH {
+            warn!(
+                "replay was recorded on {} but {} is loaded",
+                replay.level, LEVEL_PATH
+            );
+        }
+    }
+    log.replay = Replay {
+        level: LEVEL_PATH.to_string(),
+        seed: rng.seed(),
+        commands: Vec::new(),
+    };
+    log.recording = true;
+}
+
+fn save_recording(log: &CommandLog, dir: &UserDir) {
+    if !log.recording {
+        warn!("there is no recording of this battle to save");
+        return;
+    }
+    let path = dir.file(REPLAY_PATH);
+    match write_replay(&path, &log.replay) {
+        Ok(_) => info!("saved replay to {}", path.display()),
+        Err(err) => error!("could not save replay to {}: {}", path.display(), err),
+    }
+}
+
+/// Records each decision and, during a replay, checks it against the recorded one.
+fn record_commands(
+    mut commands: EventReader<BattleCommand>,
+    mut log: ResMut<CommandLog>,
+    mut playback: ResMut<Playback>,
+) {
+    for command in commands.iter() {
+        if log.recording {
+            log.replay.commands.push(*command);
+        }
+        let expected = match playback.next() {
+            Some(expected) => expected,
+            None => continue,
+        };
+        if expected == *command {
+            playback.cursor += 1;
+            if playback.next().is_none() {
+                info!("replay finished");
+                playback.stop();
+            }
+        } else {
+            warn!(
+                "replay diverged at command {}: expected {:?}, got {:?}",
+                playback.cursor, expected, command
+            );
+            playback.stop();
+        }
+    }
+}
+
+fn play_commands(
+    time: Res<Time>,
+    mut playback: ResMut<Playback>,
+    phase: Res<State<TurnPhase>>,
+    mut click: ResMut<TileClick>,
+    mut key_input: ResMut<Input<KeyCode>>,
+) {
+    if !playback.is_playing() {
+        return;
+    }
+    // the replay does the deciding, the player's own presses would make it diverge
+    for key in BATTLE_KEYS {
+        key_input.reset(key);
+    }
+    playback.since_decision += time.delta_seconds();
+    if playback.fed.take() == Some(playback.cursor) {
+        warn!(
+            "replay diverged at command {}: {:?} was refused",
+            playback.cursor,
+            playback.next()
+        );
+        playback.stop();
+        return;
+    }
+    let command = match playback.next() {
+        Some(command) if command.team() == Team::PLAYER => command,
+        Some(_) => return,
+        None => {
+            info!("replay finished");
+            playback.stop();
+            return;
+        }
+    };
+    let player_phase = matches!(
+        phase.current(),
+        TurnPhase::SelectUnit | TurnPhase::SelectMove | TurnPhase::SelectTarget
+    );
+    if !player_phase || !playback.decide() {
+        return;
+    }
+    let key = match command {
+        BattleCommand::Select { unit: tile, .. } | BattleCommand::Move { to: tile, .. } => {
+            click.tile = Some(tile);
+            playback.fed = Some(playback.cursor);
+            return;
+        }
+        // picks the target and confirms it in one go
+        BattleCommand::Attack { target: tile, .. } => {
+            click.tile = Some(tile);
+            KeyCode::Return
+        }
+        BattleCommand::Stay | BattleCommand::Wait { .. } | BattleCommand::EndTurn => KeyCode::Space,
+        BattleCommand::Back => KeyCode::Escape,
+        BattleCommand::Undo => KeyCode::U,
+    };
+    // a held key does not register as a new press
+    key_input.release(key);
+    key_input.press(key);
+    playback.fed = Some(playback.cursor);
+}
+
+/// P pauses the replay, N plays one decision while paused, F toggles fast forward,
+/// F6 saves the recording of the current battle.
+fn handle_replay_keys(
+    key_input: Res<Input<KeyCode>>,
+    mut playback: ResMut<Playback>,
+    log: Res<CommandLog>,
+    dir: Res<UserDir>,
+) {
+    if key_input.just_pressed(KeyCode::F6) {
+        save_recording(&log, &dir);
+    }
+    if !playback.is_playing() {
+        return;
+    }
+    if key_input.just_pressed(KeyCode::P) {
+        playback.paused = !playback.paused;
+        playback.steps = 0;
+    }
+    if key_input.just_pressed(KeyCode::N) && playback.paused {
+        playback.steps += 1;
+    }
+    if key_input.just_pressed(KeyCode::F) {
+        playback.fast = !playback.fast;
+    }
+}
+
+fn save_finished_battle(log: Res<CommandLog>, dir: Res<UserDir>) {
+    if log.recording {
+        save_recording(&log, &dir);
+    }
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BattleCommand>()
+            .init_resource::<CommandLog>()
+            .init_resource::<Playback>()
+            .init_resource::<UserDir>()
+            .add_system_set(
+                SystemSet::on_enter(TurnPhase::LoadLevel)
+                    .with_system(start_recording.after(crate::rng::reseed)),
+            )
+            // after the real input is read, which would otherwise clear the fed key presses
+            .add_system_to_stage(CoreStage::PreUpdate, play_commands.after(InputSystem))
+            .add_system(handle_replay_keys)
+            .add_system_to_stage(CoreStage::PostUpdate, record_commands)
+            .add_system_set(
+                SystemSet::on_enter(TurnPhase::Victory).with_system(save_finished_battle),
+            )
+            .add_system_set(
+                SystemSet::on_enter(TurnPhase::Defeat).with_system(save_finished_battle),
+            );
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{
+    level::{Level, LevelHandles},
+    states::TurnPhase,
+};
+use bevy::prelude::*;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+pub struct RngPlugin;
+
+/// Source of every random roll in a battle, so a seed replays the same battle.
+pub struct GameRng {
+    seed: u64,
+    rng: ChaCha12Rng,
+}
+
+/// Where a `GameRng` is in its stream, saved so a loaded battle rolls the same dice.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RngState {
+    pub seed: u64,
+    pub word_pos: u128,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> GameRng {
+        GameRng {
+            seed,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn state(&self) -> RngState {
+        RngState {
+            seed: self.seed,
+            word_pos: self.rng.get_word_pos(),
+        }
+    }
+
+    pub fn restore(&mut self, state: RngState) {
+        *self = GameRng::new(state.seed);
+        self.rng.set_word_pos(state.word_pos);
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> GameRng {
+        GameRng::new(rand::random())
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+/// Seed passed with `--seed` or taken from a replay, it wins over the one in the level file.
+#[derive(Default)]
+pub struct SeedArg(pub Option<u64>);
+
+/// Picks the battle seed each time a level starts, a random one unless it is pinned.
+pub fn reseed(
+    mut rng: ResMut<GameRng>,
+    arg: Res<SeedArg>,
+    handles: Res<LevelHandles>,
+    levels: Res<Assets<Level>>,
+) {
+    let level_seed = levels.get(&handles.level).and_then(|level| level.seed);
+    let seed = arg.0.or(level_seed).unwrap_or_else(rand::random);
+    info!("battle seed {}", seed);
+    *rng = GameRng::new(seed);
+}
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameRng>()
+            .init_resource::<SeedArg>()
+            .add_system_set(
+                SystemSet::on_enter(TurnPhase::LoadLevel)
+                    .with_system(reseed.before(crate::grid::create_level)),
+            );
+    }
+}
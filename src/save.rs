@@ -0,0 +1,425 @@
+use crate::{
+    ai_units::{self, DelayedSpawn, DelayedSpawns, EnemyKind, WaveIndex},
+    fog::LastSeen,
+    grid::{spawn_tile, GridConfig, GridPosition, LevelEntity, Obstacle, Tile},
+    level::{LevelHandles, Roster, UnitJson},
+    player_units::{self, RosterSlot},
+    replay::CommandLog,
+    rng::{GameRng, RngState},
+    sim::{Team, Terrain},
+    states::TurnPhase,
+    units::{Attack, Defense, Health, Movement, Spawners, Unit},
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const QUICKSAVE_PATH: &str = "saves/quicksave.json";
+pub const AUTOSAVE_PATH: &str = "saves/autosave.json";
+
+pub struct SavePlugin;
+
+/// Folder saves and replays are written under, the working directory unless set.
+#[derive(Default, Debug, Clone)]
+pub struct UserDir(pub PathBuf);
+
+impl UserDir {
+    pub fn file(&self, path: &str) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedTile {
+    pub x: i32,
+    pub y: i32,
+    pub terrain: Terrain,
+    pub obstacle: bool,
+}
+
+/// Definition a unit was spawned from, used to find its sprite again.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum UnitSource {
+    Roster(usize),
+    Enemy(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedUnit {
+    pub team: Team,
+    pub source: UnitSource,
+    pub x: i32,
+    pub y: i32,
+    pub movement: i32,
+    pub health: i32,
+    pub max_health: i32,
+    pub attack: Attack,
+    pub defense: Defense,
+    pub has_moved: bool,
+    pub has_attacked: bool,
+    /// Tile of the ghost the player keeps of an enemy out of sight.
+    #[serde(default)]
+    pub last_seen: Option<(i32, i32)>,
+}
+
+/// Everything needed to put a battle back exactly as it was.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BattleSave {
+    pub width: i32,
+    pub height: i32,
+    pub tiles: Vec<SavedTile>,
+    pub units: Vec<SavedUnit>,
+    pub player_spawns: Vec<(i32, i32)>,
+    pub ai_spawns: Vec<(i32, i32)>,
+    #[serde(default)]
+    pub spawn_zones: BTreeMap<String, Vec<(i32, i32)>>,
+    pub phase: TurnPhase,
+    pub wave_index: usize,
+    #[serde(default)]
+    pub delayed_spawns: Vec<DelayedSpawn>,
+    /// Where the dice are, so an undo or a load cannot reroll an attack.
+    pub rng: RngState,
+}
+
+/// Save read from disk, waiting for the level to be rebuilt from it.
+#[derive(Default)]
+pub struct PendingLoad(pub Option<BattleSave>);
+
+#[derive(SystemParam)]
+pub struct BattleQuery<'w, 's> {
+    grid_config: Res<'w, GridConfig>,
+    spawners: Res<'w, Spawners>,
+    wave_index: Res<'w, WaveIndex>,
+    delayed: Res<'w, DelayedSpawns>,
+    handles: Res<'w, LevelHandles>,
+    rng: Res<'w, GameRng>,
+    last_seen: Res<'w, LastSeen>,
+    tiles: Query<
+        'w,
+        's,
+        (
+            &'static GridPosition,
+            &'static Tile,
+            Option<&'static Obstacle>,
+        ),
+    >,
+    units: Query<'w, 's, SavedUnitQuery>,
+}
+
+type SavedUnitQuery = (
+    Entity,
+    &'static Unit,
+    &'static GridPosition,
+    &'static Movement,
+    &'static Health,
+    &'static Attack,
+    &'static Defense,
+    Option<&'static RosterSlot>,
+    Option<&'static EnemyKind>,
+);
+
+impl<'w, 's> BattleQuery<'w, 's> {
+    pub fn capture(&self, phase: TurnPhase) -> BattleSave {
+        let mut tiles: Vec<SavedTile> = self
+            .tiles
+            .iter()
+            .map(|(grid, tile, obstacle)| SavedTile {
+                x: grid.x,
+                y: grid.y,
+                terrain: tile.terrain,
+                obstacle: obstacle.is_some(),
+            })
+            .collect();
+        let mut units: Vec<SavedUnit> = self
+            .units
+            .iter()
+            .filter_map(
+                |(entity, unit, grid, movement, health, attack, defense, slot, kind)| {
+                    let source = match (slot, kind) {
+                        (Some(slot), _) => UnitSource::Roster(slot.0),
+                        (None, Some(kind)) => UnitSource::Enemy(
+                            self.handles
+                                .enemies
+                                .iter()
+                                .find(|(_name, handle)| **handle == kind.0)?
+                                .0
+                                .clone(),
+                        ),
+                        (None, None) => return None,
+                    };
+                    Some(SavedUnit {
+                        team: unit.team,
+                        source,
+                        x: grid.x,
+                        y: grid.y,
+                        movement: movement.distance,
+                        health: health.value,
+                        max_health: health.max,
+                        attack: attack.clone(),
+                        defense: defense.clone(),
+                        has_moved: unit.has_moved,
+                        has_attacked: unit.has_attacked,
+                        last_seen: self.last_seen.0.get(&entity).copied(),
+                    })
+                },
+            )
+            .collect();
+        // queries have no stable order, sorting keeps equal battles equal
+        tiles.sort_by_key(|tile| (tile.x, tile.y));
+        units.sort_by_key(|unit| (unit.x, unit.y));
+        BattleSave {
+            width: self.grid_config.width,
+            height: self.grid_config.height,
+            tiles,
+            units,
+            player_spawns: self.spawners.player_locations.clone(),
+            ai_spawns: self.spawners.ai_locations.clone(),
+            spawn_zones: self.spawners.zones.clone(),
+            phase,
+            wave_index: self.wave_index.0,
+            delayed_spawns: self.delayed.0.clone(),
+            rng: self.rng.state(),
+        }
+    }
+}
+
+pub fn write_save(path: &Path, save: &BattleSave) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = fs::File::create(path)?;
+    serde_json::to_writer_pretty(file, save)?;
+    Ok(())
+}
+
+pub fn read_save(path: &Path) -> io::Result<BattleSave> {
+    let file = fs::File::open(path)?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+fn save_to(path: &Path, save: &BattleSave) {
+    match write_save(path, save) {
+        Ok(_) => info!("saved battle to {}", path.display()),
+        Err(err) => error!("could not save battle to {}: {}", path.display(), err),
+    }
+}
+
+/// Saves when a player turn starts, that is when no player unit has acted yet.
+fn autosave(battle: BattleQuery, phase: Res<State<TurnPhase>>, dir: Res<UserDir>) {
+    let fresh = battle
+        .units
+        .iter()
+        .filter(|(_entity, unit, ..)| unit.team == Team::PLAYER)
+        .all(|(_entity, unit, ..)| !unit.has_moved && !unit.has_attacked);
+    if fresh {
+        save_to(&dir.file(AUTOSAVE_PATH), &battle.capture(*phase.current()));
+    }
+}
+
+/// F5 saves while picking a unit, F9 loads the quicksave and F10 the autosave.
+fn handle_save_keys(
+    key_input: Res<Input<KeyCode>>,
+    battle: BattleQuery,
+    mut pending: ResMut<PendingLoad>,
+    mut phase: ResMut<State<TurnPhase>>,
+    mut log: ResMut<CommandLog>,
+    dir: Res<UserDir>,
+) {
+    if key_input.just_pressed(KeyCode::F5) {
+        if *phase.current() == TurnPhase::SelectUnit {
+            save_to(&dir.file(QUICKSAVE_PATH), &battle.capture(*phase.current()));
+        } else {
+            warn!("the battle can only be saved while selecting a unit");
+        }
+    }
+    let load_path = if key_input.just_pressed(KeyCode::F9) {
+        Some(dir.file(QUICKSAVE_PATH))
+    } else if key_input.just_pressed(KeyCode::F10) {
+        Some(dir.file(AUTOSAVE_PATH))
+    } else {
+        None
+    };
+    let loading = matches!(
+        phase.current(),
+        TurnPhase::LoadAssets
+            | TurnPhase::LoadLevel
+            | TurnPhase::LoadSave
+            | TurnPhase::ContentError
+    );
+    if let Some(path) = load_path.filter(|_| !loading) {
+        match read_save(&path) {
+            Ok(save) => {
+                if phase.set(TurnPhase::LoadSave).is_ok() {
+                    pending.0 = Some(save);
+                    log.interrupt();
+                }
+            }
+            Err(err) => error!("could not load battle from {}: {}", path.display(), err),
+        }
+    }
+}
+
+fn restore_battle(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut grid_config: ResMut<GridConfig>,
+    mut spawners: ResMut<Spawners>,
+    mut wave_index: ResMut<WaveIndex>,
+    mut delayed: ResMut<DelayedSpawns>,
+    mut rng: ResMut<GameRng>,
+    mut last_seen: ResMut<LastSeen>,
+    pending: Res<PendingLoad>,
+    handles: Res<LevelHandles>,
+    rosters: Res<Assets<Roster>>,
+    enemies: Res<Assets<UnitJson>>,
+) {
+    let save = match &pending.0 {
+        Some(save) => save,
+        None => return,
+    };
+    grid_config.width = save.width;
+    grid_config.height = save.height;
+    spawners.player_locations = save.player_spawns.clone();
+    spawners.ai_locations = save.ai_spawns.clone();
+    spawners.zones = save.spawn_zones.clone();
+    wave_index.0 = save.wave_index;
+    delayed.0 = save.delayed_spawns.clone();
+    rng.restore(save.rng);
+
+    let tiles: Vec<Entity> = save
+        .tiles
+        .iter()
+        .map(|tile| {
+            spawn_tile(
+                grid_config.grid_to_world((tile.x, tile.y)),
+                (tile.x, tile.y),
+                &mut commands,
+                &asset_server,
+                tile.obstacle,
+                tile.terrain,
+            )
+        })
+        .collect();
+    commands
+        .spawn()
+        .insert(Name::new("MapTiles"))
+        .insert(LevelEntity)
+        .insert_bundle(SpatialBundle::default())
+        .push_children(&tiles);
+
+    let roster = rosters.get(&handles.roster);
+    let mut players = Vec::new();
+    let mut ai = Vec::new();
+    for saved in save.units.iter() {
+        let grid = (saved.x, saved.y);
+        let world = grid_config.grid_to_world(grid);
+        let unit = match &saved.source {
+            UnitSource::Roster(slot) => {
+                let (sprite, attack_sprite, vision) =
+                    match roster.and_then(|roster| roster.units.get(*slot)) {
+                        Some(unit) => (unit.sprite.clone(), unit.attack_sprite(), unit.vision),
+                        None => {
+                            warn!("roster has no unit {}, skipping it", slot);
+                            continue;
+                        }
+                    };
+                let unit = player_units::spawn_unit(
+                    world,
+                    players.len() as i32,
+                    grid,
+                    &mut commands,
+                    &asset_server,
+                    &format!("sprites/{}", sprite),
+                    &format!("sprites/{}", attack_sprite),
+                    saved.movement,
+                    saved.max_health,
+                    saved.attack.clone(),
+                    saved.defense.clone(),
+                    vision,
+                );
+                commands.entity(unit).insert(RosterSlot(*slot));
+                players.push(unit);
+                unit
+            }
+            UnitSource::Enemy(name) => {
+                let (sprite, attack_sprite, vision) = match handles.enemy(name, &enemies) {
+                    Some(unit) => (unit.sprite.clone(), unit.attack_sprite(), unit.vision),
+                    None => {
+                        warn!("enemy {} is not loaded, skipping it", name);
+                        continue;
+                    }
+                };
+                let unit = ai_units::spawn_unit(
+                    world,
+                    ai.len() as i32,
+                    grid,
+                    &mut commands,
+                    &asset_server,
+                    &format!("sprites/{}", sprite),
+                    &format!("sprites/{}", attack_sprite),
+                    saved.movement,
+                    saved.max_health,
+                    saved.attack.clone(),
+                    saved.defense.clone(),
+                    vision,
+                );
+                commands
+                    .entity(unit)
+                    .insert(EnemyKind(handles.enemies[name].clone()));
+                ai.push(unit);
+                unit
+            }
+        };
+        commands
+            .entity(unit)
+            .insert(Health {
+                max: saved.max_health,
+                value: saved.health,
+            })
+            .insert(Unit {
+                has_moved: saved.has_moved,
+                has_attacked: saved.has_attacked,
+                team: saved.team,
+            });
+        if let Some(tile) = saved.last_seen {
+            last_seen.0.insert(unit, tile);
+        }
+    }
+    for (name, units) in [("Player Units", players), ("Ai Units", ai)] {
+        commands
+            .spawn()
+            .insert(Name::new(name))
+            .insert(LevelEntity)
+            .insert_bundle(SpatialBundle::default())
+            .push_children(&units);
+    }
+}
+
+fn finish_load(mut pending: ResMut<PendingLoad>, mut phase: ResMut<State<TurnPhase>>) {
+    let next = match pending.0.take() {
+        Some(save) => save.phase,
+        None => TurnPhase::SelectUnit,
+    };
+    phase.set(next).unwrap();
+}
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingLoad>()
+            .init_resource::<UserDir>()
+            .add_system(handle_save_keys)
+            .add_system_set(SystemSet::on_enter(TurnPhase::SelectUnit).with_system(autosave))
+            .add_system_set(
+                SystemSet::on_enter(TurnPhase::LoadSave).with_system(
+                    restore_battle
+                        .after(crate::game_over::reset_level)
+                        .after(crate::fog::forget_enemies),
+                ),
+            )
+            .add_system_set(SystemSet::on_update(TurnPhase::LoadSave).with_system(finish_load));
+    }
+}
@@ -0,0 +1,1016 @@
+//! Game rules without any Bevy types: the board, movement, combat, turns and ai decisions.
+//! The plugins hand a `Battle` built from the units on the board every rule, so whole battles
+//! can also be played without an `App`.
+
+use priority_queue::PriorityQueue;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+pub type Coord = (i32, i32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Team {
+    PLAYER,
+    AI,
+}
+
+impl Team {
+    pub fn opponent(&self) -> Team {
+        match self {
+            Team::PLAYER => Team::AI,
+            Team::AI => Team::PLAYER,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Terrain {
+    #[default]
+    Grass,
+    Mud,
+    Water,
+    Road,
+    Rubble,
+}
+
+impl Terrain {
+    pub fn from_char(c: char) -> Terrain {
+        match c {
+            ',' => Terrain::Mud,
+            '~' => Terrain::Water,
+            '=' => Terrain::Road,
+            '%' => Terrain::Rubble,
+            _ => Terrain::Grass,
+        }
+    }
+
+    pub fn cost(&self) -> i32 {
+        match self {
+            Terrain::Grass | Terrain::Road => 1,
+            Terrain::Mud | Terrain::Rubble => 2,
+            Terrain::Water => 3,
+        }
+    }
+}
+
+/// Which tiles can be entered and what they cost. Units standing on a tile count as blocking,
+/// only obstacles block sight.
+#[derive(Default, Debug, Clone)]
+pub struct Board {
+    blocked: HashMap<Coord, bool>,
+    costs: HashMap<Coord, i32>,
+    obstacles: HashSet<Coord>,
+}
+
+#[derive(Default, Debug)]
+pub struct ReachableTiles(pub HashMap<Coord, ReachedTile>);
+
+type ReachedTile = (i32, Option<Coord>);
+
+impl ReachableTiles {
+    pub fn cost(&self, tile: Coord) -> Option<i32> {
+        self.0.get(&tile).map(|(cost, _)| *cost)
+    }
+}
+
+impl Board {
+    pub fn set_tile(&mut self, tile: Coord, blocked: bool, cost: i32) {
+        self.blocked.insert(tile, blocked);
+        self.costs.insert(tile, cost);
+    }
+
+    pub fn set_blocked(&mut self, tile: Coord, blocked: bool) {
+        if let Some(is_blocked) = self.blocked.get_mut(&tile) {
+            *is_blocked = blocked;
+        }
+    }
+
+    pub fn set_obstacle(&mut self, tile: Coord) {
+        self.obstacles.insert(tile);
+    }
+
+    /// Whether no obstacle stands on the straight line between two tiles. The line is traced
+    /// from both ends and either one being clear will do, so sight works the same both ways.
+    pub fn line_of_sight(&self, from: Coord, to: Coord) -> bool {
+        let clear = |line: Vec<Coord>| {
+            line.iter()
+                .all(|tile| *tile == from || *tile == to || !self.obstacles.contains(tile))
+        };
+        clear(line(from, to)) || clear(line(to, from))
+    }
+
+    pub fn can_attack(&self, from: Coord, to: Coord, range: i32) -> bool {
+        in_attack_range(from, to, range) && self.line_of_sight(from, to)
+    }
+
+    pub fn contains(&self, tile: Coord) -> bool {
+        self.blocked.contains_key(&tile)
+    }
+
+    pub fn is_open(&self, tile: Coord) -> bool {
+        self.blocked.get(&tile) == Some(&false)
+    }
+
+    pub fn cost(&self, tile: Coord) -> i32 {
+        *self.costs.get(&tile).unwrap_or(&1)
+    }
+
+    pub fn reachable_tiles(&self, from: Coord, budget: i32) -> ReachableTiles {
+        let mut open_set: PriorityQueue<Coord, Reverse<i32>> = PriorityQueue::new();
+        let mut reachable: HashMap<Coord, ReachedTile> = HashMap::new();
+
+        open_set.push(from, Reverse(0));
+        reachable.insert(from, (0, None));
+
+        while let Some((current, Reverse(current_cost))) = open_set.pop() {
+            for next in adjacents(current) {
+                if !self.is_open(next) {
+                    continue;
+                }
+                let new_cost = current_cost + self.cost(next);
+                if new_cost > budget {
+                    continue;
+                }
+                let improved = match reachable.get(&next) {
+                    Some((cost, _)) => new_cost < *cost,
+                    None => true,
+                };
+                if improved {
+                    reachable.insert(next, (new_cost, Some(current)));
+                    open_set.push(next, Reverse(new_cost));
+                }
+            }
+        }
+        ReachableTiles(reachable)
+    }
+
+    /// Cheapest path from `from` to `to`, listed from the destination back to the first step.
+    pub fn path(&self, from: Coord, to: Coord) -> Vec<Coord> {
+        let mut open_set: PriorityQueue<Coord, Reverse<i32>> = PriorityQueue::new();
+        let mut closed_set: HashMap<Coord, Option<Coord>> = HashMap::new();
+        let mut current_costs: HashMap<Coord, i32> = HashMap::new();
+
+        open_set.push(from, Reverse(0));
+        closed_set.insert(from, None);
+        current_costs.insert(from, 0);
+
+        while let Some((current, _priority)) = open_set.pop() {
+            if current == to {
+                return get_path(&closed_set, from, current);
+            }
+
+            for next in adjacents(current) {
+                // the mover's own tile is occupied by itself
+                let enterable = self.is_open(next) || (next == from && self.contains(next));
+                if !enterable {
+                    continue;
+                }
+                let new_cost = current_costs[&current] + self.cost(next);
+                if !current_costs.contains_key(&next) || new_cost < current_costs[&next] {
+                    current_costs.insert(next, new_cost);
+                    let priority = new_cost + heuristic(to, next);
+                    open_set.push(next, Reverse(priority));
+                    closed_set.insert(next, Some(current));
+                }
+            }
+        }
+        Vec::new()
+    }
+}
+
+fn get_path(closed_set: &HashMap<Coord, Option<Coord>>, from: Coord, to: Coord) -> Vec<Coord> {
+    let mut path: Vec<Coord> = Vec::new();
+    let mut current_tile = to;
+    while current_tile != from {
+        path.push(current_tile);
+        match closed_set[&current_tile] {
+            Some(previous) => current_tile = previous,
+            None => break,
+        }
+    }
+    path
+}
+
+fn adjacents(tile: Coord) -> [Coord; 4] {
+    [
+        (tile.0, tile.1 + 1),
+        (tile.0, tile.1 - 1),
+        (tile.0 + 1, tile.1),
+        (tile.0 - 1, tile.1),
+    ]
+}
+
+pub fn visible_tiles(board: &Board, viewers: &[(Coord, i32)]) -> HashSet<Coord> {
+    let mut visible = HashSet::new();
+    for &(from, radius) in viewers {
+        for x in from.0 - radius..=from.0 + radius {
+            for y in from.1 - radius..=from.1 + radius {
+                if board.contains((x, y)) && board.line_of_sight(from, (x, y)) {
+                    visible.insert((x, y));
+                }
+            }
+        }
+    }
+    visible
+}
+
+fn line(from: Coord, to: Coord) -> Vec<Coord> {
+    let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let mut error = dx + dy;
+    let mut tile = from;
+    let mut tiles = vec![tile];
+    while tile != to {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            tile.0 += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            tile.1 += step_y;
+        }
+        tiles.push(tile);
+    }
+    tiles
+}
+
+fn heuristic(goal: Coord, next_step: Coord) -> i32 {
+    (goal.0 - next_step.0).abs() + (goal.1 - next_step.1).abs()
+}
+
+/// Tiles between two units, diagonals count as one step.
+pub fn distance(a: Coord, b: Coord) -> i32 {
+    std::cmp::max(i32::abs(a.0 - b.0), i32::abs(a.1 - b.1))
+}
+
+pub fn in_attack_range(from: Coord, to: Coord, range: i32) -> bool {
+    let dist = distance(from, to);
+    dist > 0 && dist <= range
+}
+
+pub const CRIT_MULTIPLIER: i32 = 2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DamageType {
+    #[default]
+    Slash,
+    Pierce,
+    Blunt,
+    Fire,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Affinity {
+    Resistant,
+    #[default]
+    Neutral,
+    Weak,
+}
+
+impl Affinity {
+    /// Resistant takes half and weak half again as much, both rounded down.
+    pub fn scale(&self, damage: i32) -> i32 {
+        match self {
+            Affinity::Resistant => damage / 2,
+            Affinity::Neutral => damage,
+            Affinity::Weak => damage * 3 / 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Armor {
+    pub defense: i32,
+    pub resistances: Vec<DamageType>,
+    pub weaknesses: Vec<DamageType>,
+}
+
+impl Armor {
+    pub fn affinity(&self, damage_type: DamageType) -> Affinity {
+        match (
+            self.resistances.contains(&damage_type),
+            self.weaknesses.contains(&damage_type),
+        ) {
+            (true, false) => Affinity::Resistant,
+            (false, true) => Affinity::Weak,
+            _ => Affinity::Neutral,
+        }
+    }
+
+    /// Damage a hit still does through the armor, a hit always does at least 1.
+    pub fn mitigate(&self, damage: i32, damage_type: DamageType) -> i32 {
+        (self.affinity(damage_type).scale(damage) - self.defense).max(1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Odds {
+    pub hit: i32,
+    pub crit: i32,
+    pub min_damage: i32,
+    pub max_damage: i32,
+    pub affinity: Affinity,
+}
+
+impl Odds {
+    /// The attacker's accuracy less the target's evasion is the chance to hit.
+    pub fn new(accuracy: i32, evasion: i32, crit: i32, min_damage: i32, max_damage: i32) -> Odds {
+        Odds {
+            hit: (accuracy - evasion).clamp(0, 100),
+            crit: crit.clamp(0, 100),
+            min_damage,
+            max_damage: max_damage.max(min_damage),
+            affinity: Affinity::Neutral,
+        }
+    }
+
+    pub fn against(self, armor: &Armor, damage_type: DamageType) -> Odds {
+        Odds {
+            min_damage: armor.mitigate(self.min_damage, damage_type),
+            max_damage: armor.mitigate(self.max_damage, damage_type),
+            affinity: armor.affinity(damage_type),
+            ..self
+        }
+    }
+
+    pub fn expected_damage(&self) -> f32 {
+        let average = (self.min_damage + self.max_damage) as f32 / 2.0;
+        let crit_bonus = self.crit as f32 / 100.0 * (CRIT_MULTIPLIER - 1) as f32;
+        self.hit as f32 / 100.0 * average * (1.0 + crit_bonus)
+    }
+
+    pub fn roll(&self, rng: &mut impl Rng) -> Roll {
+        if rng.gen_range(0..100) >= self.hit {
+            return Roll {
+                hit: false,
+                crit: false,
+                damage: 0,
+            };
+        }
+        let damage = rng.gen_range(self.min_damage..=self.max_damage);
+        let crit = rng.gen_range(0..100) < self.crit;
+        Roll {
+            hit: true,
+            crit,
+            damage: if crit {
+                damage * CRIT_MULTIPLIER
+            } else {
+                damage
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Roll {
+    pub hit: bool,
+    pub crit: bool,
+    pub damage: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttackResult {
+    pub damage: i32,
+    pub remaining: i32,
+    pub killed: bool,
+}
+
+pub fn resolve_attack(damage: i32, target_health: i32) -> AttackResult {
+    let remaining = target_health - damage;
+    AttackResult {
+        damage,
+        remaining,
+        killed: remaining <= 0,
+    }
+}
+
+/// A forecast counts on regular hits for their lowest damage, `odds` and `counter_odds` tell
+/// how likely that is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Forecast {
+    pub attacker_health: i32,
+    pub target_health: i32,
+    pub odds: Odds,
+    pub attack: AttackResult,
+    pub counter_odds: Option<Odds>,
+    pub counter: Option<AttackResult>,
+}
+
+impl Forecast {
+    pub fn attacker_after(&self) -> i32 {
+        self.counter
+            .map_or(self.attacker_health, |counter| counter.remaining)
+    }
+}
+
+pub fn forecast_attack(
+    attacker_health: i32,
+    odds: Odds,
+    target_health: i32,
+    counter: Option<Odds>,
+) -> Forecast {
+    let attack = resolve_attack(odds.min_damage, target_health);
+    let counter_odds = counter.filter(|_odds| !attack.killed);
+    Forecast {
+        attacker_health,
+        target_health,
+        odds,
+        attack,
+        counter_odds,
+        counter: counter_odds.map(|counter| resolve_attack(counter.min_damage, attacker_health)),
+    }
+}
+
+pub fn roll_attack(
+    odds: Odds,
+    target_health: i32,
+    counter: Option<Odds>,
+    rng: &mut impl Rng,
+) -> (Roll, Option<Roll>) {
+    let roll = odds.roll(rng);
+    let counter = counter
+        .filter(|_odds| target_health > roll.damage)
+        .map(|counter| counter.roll(rng));
+    (roll, counter)
+}
+
+pub fn can_counter(
+    board: &Board,
+    defender: Coord,
+    attacker: Coord,
+    range: i32,
+    counterattacks: bool,
+) -> bool {
+    counterattacks && board.can_attack(defender, attacker, range)
+}
+
+/// Where an ai unit walks: it stays if it can already hit a target, otherwise it takes the
+/// reachable tile with the shortest walk to any target, preferring tiles it can attack from.
+pub fn choose_ai_move(
+    board: &Board,
+    from: Coord,
+    movement: i32,
+    range: i32,
+    targets: &[Coord],
+) -> Coord {
+    let can_attack_from = |tile: Coord| {
+        targets
+            .iter()
+            .any(|target| board.can_attack(tile, *target, range))
+    };
+    if can_attack_from(from) {
+        return from;
+    }
+    let reachable = board.reachable_tiles(from, movement);
+    // one unbounded fill per target gives the distance from every tile to them
+    let target_fields: Vec<ReachableTiles> = targets
+        .iter()
+        .map(|target| board.reachable_tiles(*target, i32::MAX))
+        .collect();
+    reachable
+        .0
+        .keys()
+        .filter_map(|tile| {
+            target_fields
+                .iter()
+                .filter_map(|field| field.cost(*tile))
+                .min()
+                .map(|cost| (!can_attack_from(*tile), cost, *tile))
+        })
+        .min()
+        .map_or(from, |(_blocked, _cost, tile)| tile)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AiTarget {
+    pub pos: Coord,
+    pub health: i32,
+    pub odds: Odds,
+}
+
+/// Index of the target the ai unit can hit that loses the largest share of its health on
+/// average, so a likely kill beats a big hit on a healthy unit. The first one wins a tie.
+pub fn choose_ai_target(
+    board: &Board,
+    from: Coord,
+    range: i32,
+    targets: &[AiTarget],
+) -> Option<usize> {
+    let share = |target: &AiTarget| {
+        let health = target.health.max(1) as f32;
+        target.odds.expected_damage().min(health) / health
+    };
+    targets
+        .iter()
+        .enumerate()
+        .filter(|(_i, target)| board.can_attack(from, target.pos, range))
+        .fold(None, |best: Option<(usize, f32)>, (i, target)| match best {
+            Some((_best, best_share)) if best_share >= share(target) => best,
+            _ => Some((i, share(target))),
+        })
+        .map(|(i, _share)| i)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimUnit {
+    pub team: Team,
+    pub pos: Coord,
+    pub movement: i32,
+    pub health: i32,
+    pub vision: i32,
+    pub damage: i32,
+    pub max_damage: i32,
+    pub range: i32,
+    pub accuracy: i32,
+    pub evasion: i32,
+    pub crit: i32,
+    pub damage_type: DamageType,
+    pub armor: Armor,
+    pub counterattack: bool,
+    pub has_moved: bool,
+    pub has_attacked: bool,
+}
+
+impl SimUnit {
+    pub fn is_alive(&self) -> bool {
+        self.health > 0
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.has_moved && self.has_attacked
+    }
+
+    pub fn odds_against(&self, target: &SimUnit) -> Odds {
+        Odds::new(
+            self.accuracy,
+            target.evasion,
+            self.crit,
+            self.damage,
+            self.max_damage,
+        )
+        .against(&target.armor, self.damage_type)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleError {
+    NoSuchUnit,
+    NotYourTurn,
+    AlreadyMoved,
+    AlreadyAttacked,
+    Unreachable,
+    SameTeam,
+    OutOfRange,
+    OutOfSight,
+    Unseen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exchange {
+    pub roll: Roll,
+    pub attack: AttackResult,
+    pub counter: Option<(Roll, AttackResult)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Battle {
+    pub board: Board,
+    pub units: Vec<SimUnit>,
+    pub turn: Team,
+}
+
+impl Battle {
+    pub fn new(board: Board, units: Vec<SimUnit>) -> Battle {
+        Battle {
+            board,
+            units,
+            turn: Team::PLAYER,
+        }
+    }
+
+    pub fn occupied_board(&self) -> Board {
+        let mut board = self.board.clone();
+        for unit in self.units.iter().filter(|unit| unit.is_alive()) {
+            board.set_blocked(unit.pos, true);
+        }
+        board
+    }
+
+    pub fn sight(&self, team: Team) -> HashSet<Coord> {
+        let viewers: Vec<(Coord, i32)> = self
+            .units
+            .iter()
+            .filter(|unit| unit.team == team && unit.is_alive())
+            .map(|unit| (unit.pos, unit.vision))
+            .collect();
+        visible_tiles(&self.board, &viewers)
+    }
+
+    pub fn unit_at(&self, tile: Coord) -> Option<usize> {
+        self.units
+            .iter()
+            .position(|unit| unit.pos == tile && unit.is_alive())
+    }
+
+    fn acting_unit(&self, i: usize) -> Result<&SimUnit, RuleError> {
+        match self.units.get(i) {
+            Some(unit) if unit.is_alive() => {
+                if unit.team == self.turn {
+                    Ok(unit)
+                } else {
+                    Err(RuleError::NotYourTurn)
+                }
+            }
+            _ => Err(RuleError::NoSuchUnit),
+        }
+    }
+
+    /// Moving onto its own tile is how a unit stays put.
+    pub fn move_unit(&mut self, i: usize, to: Coord) -> Result<(), RuleError> {
+        let unit = self.acting_unit(i)?;
+        if unit.has_moved {
+            return Err(RuleError::AlreadyMoved);
+        }
+        if to != unit.pos {
+            let reachable = self
+                .occupied_board()
+                .reachable_tiles(unit.pos, unit.movement);
+            if reachable.cost(to).is_none() {
+                return Err(RuleError::Unreachable);
+            }
+        }
+        let unit = &mut self.units[i];
+        unit.pos = to;
+        unit.has_moved = true;
+        Ok(())
+    }
+
+    fn check_target(&self, i: usize, target: usize) -> Result<(), RuleError> {
+        let unit = &self.units[i];
+        let defender = match self.units.get(target) {
+            Some(defender) if defender.is_alive() => defender,
+            _ => return Err(RuleError::NoSuchUnit),
+        };
+        if defender.team == unit.team {
+            Err(RuleError::SameTeam)
+        } else if !in_attack_range(unit.pos, defender.pos, unit.range) {
+            Err(RuleError::OutOfRange)
+        } else if !self.board.line_of_sight(unit.pos, defender.pos) {
+            Err(RuleError::OutOfSight)
+        } else if !self.sight(unit.team).contains(&defender.pos) {
+            Err(RuleError::Unseen)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn can_attack(&self, i: usize, target: usize) -> bool {
+        self.units.get(i).map_or(false, |unit| unit.is_alive())
+            && self.check_target(i, target).is_ok()
+    }
+
+    fn counter_odds(&self, i: usize, target: usize) -> Option<Odds> {
+        let (unit, defender) = (&self.units[i], &self.units[target]);
+        can_counter(
+            &self.board,
+            defender.pos,
+            unit.pos,
+            defender.range,
+            defender.counterattack,
+        )
+        .then(|| defender.odds_against(unit))
+    }
+
+    pub fn forecast(&self, i: usize, target: usize) -> Forecast {
+        let (unit, defender) = (&self.units[i], &self.units[target]);
+        forecast_attack(
+            unit.health,
+            unit.odds_against(defender),
+            defender.health,
+            self.counter_odds(i, target),
+        )
+    }
+
+    pub fn attack(
+        &mut self,
+        i: usize,
+        target: usize,
+        rng: &mut impl Rng,
+    ) -> Result<Exchange, RuleError> {
+        if self.acting_unit(i)?.has_attacked {
+            return Err(RuleError::AlreadyAttacked);
+        }
+        self.check_target(i, target)?;
+        let (unit, defender) = (&self.units[i], &self.units[target]);
+        let (roll, counter) = roll_attack(
+            unit.odds_against(defender),
+            defender.health,
+            self.counter_odds(i, target),
+            rng,
+        );
+        let exchange = Exchange {
+            roll,
+            attack: resolve_attack(roll.damage, defender.health),
+            counter: counter.map(|counter| (counter, resolve_attack(counter.damage, unit.health))),
+        };
+        self.units[target].health = exchange.attack.remaining;
+        let unit = &mut self.units[i];
+        if let Some((_roll, counter)) = exchange.counter {
+            unit.health = counter.remaining;
+        }
+        unit.has_moved = true;
+        unit.has_attacked = true;
+        Ok(exchange)
+    }
+
+    pub fn wait(&mut self, i: usize) -> Result<(), RuleError> {
+        self.acting_unit(i)?;
+        let unit = &mut self.units[i];
+        unit.has_moved = true;
+        unit.has_attacked = true;
+        Ok(())
+    }
+
+    /// Whether every living unit of the side playing has acted, a side without any has.
+    pub fn turn_over(&self) -> bool {
+        self.units
+            .iter()
+            .filter(|unit| unit.team == self.turn && unit.is_alive())
+            .all(|unit| unit.is_done())
+    }
+
+    pub fn start_turn(&mut self, team: Team) {
+        for unit in self.units.iter_mut() {
+            unit.has_moved = false;
+            unit.has_attacked = false;
+        }
+        self.turn = team;
+    }
+
+    pub fn end_turn(&mut self) {
+        self.start_turn(self.turn.opponent());
+    }
+
+    fn enemies_in(&self, i: usize, sight: &HashSet<Coord>) -> Vec<usize> {
+        let team = self.units[i].team;
+        (0..self.units.len())
+            .filter(|enemy| {
+                let enemy = &self.units[*enemy];
+                enemy.team != team && enemy.is_alive() && sight.contains(&enemy.pos)
+            })
+            .collect()
+    }
+
+    pub fn ai_move(&self, i: usize, sight: &HashSet<Coord>) -> Coord {
+        let unit = &self.units[i];
+        let targets: Vec<Coord> = self
+            .enemies_in(i, sight)
+            .iter()
+            .map(|enemy| self.units[*enemy].pos)
+            .collect();
+        choose_ai_move(
+            &self.occupied_board(),
+            unit.pos,
+            unit.movement,
+            unit.range,
+            &targets,
+        )
+    }
+
+    pub fn ai_target(&self, i: usize, sight: &HashSet<Coord>) -> Option<usize> {
+        let unit = &self.units[i];
+        let enemies = self.enemies_in(i, sight);
+        let targets: Vec<AiTarget> = enemies
+            .iter()
+            .map(|enemy| AiTarget {
+                pos: self.units[*enemy].pos,
+                health: self.units[*enemy].health,
+                odds: unit.odds_against(&self.units[*enemy]),
+            })
+            .collect();
+        choose_ai_target(&self.board, unit.pos, unit.range, &targets).map(|target| enemies[target])
+    }
+
+    pub fn play_ai_turn(&mut self, rng: &mut impl Rng) {
+        for i in 0..self.units.len() {
+            if self.acting_unit(i).map_or(true, |unit| unit.is_done()) {
+                continue;
+            }
+            if !self.units[i].has_moved {
+                let to = self.ai_move(i, &self.sight(self.turn));
+                self.move_unit(i, to).unwrap();
+            }
+            match self.ai_target(i, &self.sight(self.turn)) {
+                Some(target) => {
+                    self.attack(i, target, rng).unwrap();
+                }
+                None => self.wait(i).unwrap(),
+            }
+        }
+        self.end_turn();
+    }
+
+    /// The side left standing, the player loses once it has no units even if the ai has none.
+    pub fn winner(&self) -> Option<Team> {
+        let alive = |team| {
+            self.units
+                .iter()
+                .any(|unit| unit.team == team && unit.is_alive())
+        };
+        if !alive(Team::PLAYER) {
+            Some(Team::AI)
+        } else if !alive(Team::AI) {
+            Some(Team::PLAYER)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    fn open_board(width: i32, height: i32) -> Board {
+        let mut board = Board::default();
+        for x in 0..width {
+            for y in 0..height {
+                board.set_tile((x, y), false, 1);
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn reachable_tiles_pay_for_terrain_and_avoid_blocked_tiles() {
+        let mut board = open_board(3, 3);
+        board.set_tile((1, 0), false, Terrain::Mud.cost());
+        board.set_blocked((0, 1), true);
+
+        let reachable = board.reachable_tiles((0, 0), 2);
+        assert_eq!(reachable.cost((0, 0)), Some(0));
+        assert_eq!(reachable.cost((1, 0)), Some(2));
+        assert_eq!(reachable.cost((0, 1)), None);
+        // past the mud the budget runs out
+        assert_eq!(reachable.cost((1, 1)), None);
+        assert_eq!(reachable.0.len(), 2);
+        assert_eq!(reachable.0[&(1, 0)].1, Some((0, 0)));
+    }
+
+    #[test]
+    fn paths_go_around_blocked_tiles() {
+        let mut board = open_board(3, 3);
+        board.set_blocked((1, 1), true);
+        // the mover blocks its own tile
+        board.set_blocked((0, 1), true);
+
+        let path = board.path((0, 1), (2, 1));
+        assert_eq!(path.len(), 4);
+        assert_eq!(path[0], (2, 1));
+        assert_eq!(distance(*path.last().unwrap(), (0, 1)), 1);
+        assert!(path.iter().all(|tile| board.is_open(*tile)));
+        for step in path.windows(2) {
+            assert_eq!(heuristic(step[0], step[1]), 1);
+        }
+        assert_eq!(board.path((0, 0), (5, 5)), Vec::new());
+    }
+
+    #[test]
+    fn paths_prefer_cheap_terrain() {
+        let mut board = open_board(3, 2);
+        board.set_tile((1, 0), false, 5);
+        assert_eq!(
+            board.path((0, 0), (2, 0)),
+            vec![(2, 0), (2, 1), (1, 1), (0, 1)]
+        );
+    }
+
+    #[test]
+    fn ai_units_stay_when_a_target_is_in_range() {
+        let board = open_board(5, 5);
+        assert_eq!(choose_ai_move(&board, (2, 2), 3, 1, &[(3, 3)]), (2, 2));
+    }
+
+    #[test]
+    fn ai_units_walk_towards_the_nearest_target() {
+        let mut board = open_board(6, 1);
+        // targets stand on their tiles
+        board.set_blocked((5, 0), true);
+        assert_eq!(choose_ai_move(&board, (0, 0), 2, 1, &[(5, 0)]), (2, 0));
+        assert_eq!(choose_ai_move(&board, (0, 0), 2, 1, &[]), (0, 0));
+    }
+
+    #[test]
+    fn ai_units_prefer_tiles_they_can_attack_from() {
+        let mut board = open_board(5, 5);
+        board.set_blocked((2, 2), true);
+        // (0, 2), (1, 1) and (2, 0) are all two steps from the target, only the diagonal is
+        // close enough to strike
+        assert_eq!(choose_ai_move(&board, (0, 0), 2, 1, &[(2, 2)]), (1, 1));
+    }
+
+    #[test]
+    fn attacks_resolve_into_remaining_health() {
+        assert_eq!(
+            resolve_attack(4, 10),
+            AttackResult {
+                damage: 4,
+                remaining: 6,
+                killed: false,
+            }
+        );
+        assert!(resolve_attack(10, 10).killed);
+        assert_eq!(resolve_attack(12, 10).remaining, -2);
+    }
+
+    fn fighter(team: Team, pos: Coord) -> SimUnit {
+        SimUnit {
+            team,
+            pos,
+            movement: 3,
+            health: 12,
+            vision: 9,
+            damage: 3,
+            max_damage: 5,
+            range: 1,
+            accuracy: 85,
+            evasion: 10,
+            crit: 10,
+            damage_type: DamageType::Slash,
+            armor: Armor::default(),
+            counterattack: true,
+            has_moved: false,
+            has_attacked: false,
+        }
+    }
+
+    #[test]
+    fn battles_enforce_turns_range_and_sight() {
+        let mut board = open_board(5, 5);
+        board.set_obstacle((2, 1));
+        let mut battle = Battle::new(
+            board,
+            vec![fighter(Team::PLAYER, (0, 0)), fighter(Team::AI, (4, 0))],
+        );
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+        assert_eq!(battle.move_unit(1, (3, 0)), Err(RuleError::NotYourTurn));
+        assert_eq!(battle.move_unit(0, (4, 4)), Err(RuleError::Unreachable));
+        assert_eq!(battle.attack(0, 1, &mut rng), Err(RuleError::OutOfRange));
+        battle.move_unit(0, (3, 0)).unwrap();
+        assert_eq!(battle.move_unit(0, (2, 0)), Err(RuleError::AlreadyMoved));
+        assert_eq!(battle.attack(0, 0, &mut rng), Err(RuleError::SameTeam));
+
+        battle.units[0].range = 3;
+        battle.units[0].pos = (1, 1);
+        battle.units[1].pos = (3, 1);
+        assert_eq!(battle.attack(0, 1, &mut rng), Err(RuleError::OutOfSight));
+        battle.units[0].vision = 1;
+        battle.units[1].pos = (3, 2);
+        assert_eq!(battle.attack(0, 1, &mut rng), Err(RuleError::Unseen));
+
+        battle.wait(0).unwrap();
+        assert!(battle.turn_over());
+        battle.end_turn();
+        assert_eq!(battle.turn, Team::AI);
+        assert!(!battle.units[0].is_done());
+    }
+
+    #[test]
+    fn a_seeded_battle_plays_out_to_a_winner() {
+        let play = |seed: u64| {
+            let mut board = open_board(9, 9);
+            for wall in [(3, 4), (4, 4), (5, 4)] {
+                board.set_blocked(wall, true);
+                board.set_obstacle(wall);
+            }
+            let mut units = Vec::new();
+            for x in [2, 4, 6] {
+                units.push(fighter(Team::PLAYER, (x, 0)));
+                units.push(fighter(Team::AI, (x, 8)));
+            }
+            units[1].range = 3;
+            units[1].counterattack = false;
+            let mut battle = Battle::new(board, units);
+            let mut rng = ChaCha12Rng::seed_from_u64(seed);
+            let mut turns = 0;
+            // both sides are played by the ai rules
+            while battle.winner().is_none() {
+                assert!(turns < 200, "no winner after {} turns", turns);
+                battle.play_ai_turn(&mut rng);
+                turns += 1;
+            }
+            let healths: Vec<i32> = battle.units.iter().map(|unit| unit.health).collect();
+            (battle.winner(), turns, healths)
+        };
+        let (winner, turns, healths) = play(7);
+        assert!(winner.is_some());
+        assert!(turns > 1);
+        assert_eq!(play(7), (winner, turns, healths));
+    }
+}
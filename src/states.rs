@@ -1,15 +1,25 @@
-#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum TurnPhase {
+    LoadAssets,
+    LoadLevel,
+    LoadSave,
+
     SelectUnit,
     SelectMove,
     DoMove,
-    SelectAttacker,
     SelectTarget,
+    DoAttack,
 
     AiSpawnWave,
     AISelectUnit,
     AISelectMove,
     AIDoMove,
-    AISelectAttacker,
     AISelectTarget,
+    AIDoAttack,
+
+    Victory,
+    Defeat,
+    ContentError,
 }
@@ -0,0 +1,101 @@
+use crate::{
+    level::{Level, LevelHandles},
+    replay::BattleCommand,
+    save::{BattleQuery, BattleSave, PendingLoad},
+    states::TurnPhase,
+};
+use bevy::prelude::*;
+
+pub struct UndoPlugin;
+
+/// Battle as it was before each player action this turn, newest last.
+#[derive(Default)]
+pub struct UndoStack {
+    snapshots: Vec<BattleSave>,
+    used: usize,
+    restoring: bool,
+}
+
+/// Remembers the battle whenever the player is back to picking a unit and something changed.
+fn push_snapshot(battle: BattleQuery, mut undo: ResMut<UndoStack>) {
+    let snapshot = battle.capture(TurnPhase::SelectUnit);
+    if undo.snapshots.last() != Some(&snapshot) {
+        undo.snapshots.push(snapshot);
+    }
+}
+
+fn clear_snapshots(mut undo: ResMut<UndoStack>) {
+    undo.snapshots.clear();
+}
+
+fn reset_undos(mut undo: ResMut<UndoStack>) {
+    undo.snapshots.clear();
+    undo.used = 0;
+}
+
+/// A save loaded from disk starts a new history, restoring a snapshot keeps it.
+fn on_load_save(mut undo: ResMut<UndoStack>) {
+    if !undo.restoring {
+        undo.snapshots.clear();
+    }
+    undo.restoring = false;
+}
+
+/// U takes back the unfinished action, or the last finished one if nothing changed yet.
+fn undo_last_action(
+    key_input: Res<Input<KeyCode>>,
+    battle: BattleQuery,
+    mut undo: ResMut<UndoStack>,
+    mut pending: ResMut<PendingLoad>,
+    mut phase: ResMut<State<TurnPhase>>,
+    handles: Res<LevelHandles>,
+    levels: Res<Assets<Level>>,
+    mut decisions: EventWriter<BattleCommand>,
+) {
+    if !key_input.just_pressed(KeyCode::U) {
+        return;
+    }
+    if !matches!(
+        phase.current(),
+        TurnPhase::SelectUnit | TurnPhase::SelectMove | TurnPhase::SelectTarget
+    ) {
+        return;
+    }
+    let limit = levels
+        .get(&handles.level)
+        .and_then(|level| level.undo_limit);
+    if matches!(limit, Some(limit) if undo.used >= limit) {
+        info!("no undos left for this level");
+        return;
+    }
+    if undo.snapshots.last() == Some(&battle.capture(TurnPhase::SelectUnit)) {
+        if undo.snapshots.len() < 2 {
+            info!("nothing to undo");
+            return;
+        }
+        undo.snapshots.pop();
+    }
+    let snapshot = match undo.snapshots.last() {
+        Some(snapshot) => snapshot.clone(),
+        None => return,
+    };
+    if phase.set(TurnPhase::LoadSave).is_ok() {
+        decisions.send(BattleCommand::Undo);
+        pending.0 = Some(snapshot);
+        undo.used += 1;
+        undo.restoring = true;
+    }
+}
+
+impl Plugin for UndoPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<UndoStack>()
+            .add_system(undo_last_action)
+            .add_system_set(SystemSet::on_enter(TurnPhase::SelectUnit).with_system(push_snapshot))
+            .add_system_set(
+                SystemSet::on_enter(TurnPhase::AISelectUnit).with_system(clear_snapshots),
+            )
+            .add_system_set(SystemSet::on_enter(TurnPhase::LoadLevel).with_system(reset_undos))
+            .add_system_set(SystemSet::on_enter(TurnPhase::LoadSave).with_system(on_load_save));
+    }
+}
@@ -1,21 +1,32 @@
-use bevy::{prelude::*, render::camera::RenderTarget};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-use crate::{camera::MainCamera, grid::GridPosition, states::TurnPhase};
+use crate::{
+    fog::TeamVision,
+    grid::GridPosition,
+    level::UnitJson,
+    replay::BattleCommand,
+    sim::{Armor, Battle, Board, DamageType, SimUnit, Team},
+    states::TurnPhase,
+};
 
 pub struct UnitsPlugin;
 
-#[derive(Debug, PartialEq)]
-pub enum Team {
-    PLAYER,
-    AI,
-}
-
 #[derive(Component, Debug)]
 pub struct Unit {
-    pub has_acted: bool,
+    pub has_moved: bool,
+    pub has_attacked: bool,
     pub team: Team,
 }
 
+impl Unit {
+    /// A unit has finished its turn once it can neither move nor attack.
+    pub fn is_done(&self) -> bool {
+        self.has_moved && self.has_attacked
+    }
+}
+
 #[derive(Component, Debug)]
 pub struct Movement {
     pub distance: i32,
@@ -27,11 +38,86 @@ pub struct Health {
     pub value: i32,
 }
 
-#[derive(Component)]
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Attack {
     pub dmg: i32,
+    /// Highest damage of a hit, hits roll between `dmg` and this.
+    pub max_dmg: i32,
     pub range: i32,
+    /// Percent chance to hit a target without evasion.
+    pub accuracy: i32,
+    /// Percent chance for a hit to be critical.
+    pub crit: i32,
+    pub damage_type: DamageType,
+    /// Strikes back when attacked from within `range`.
+    pub counter: bool,
+}
+
+impl From<&UnitJson> for Attack {
+    fn from(unit: &UnitJson) -> Attack {
+        Attack {
+            dmg: unit.damage,
+            max_dmg: unit.max_damage(),
+            range: unit.range,
+            accuracy: unit.accuracy,
+            crit: unit.crit,
+            damage_type: unit.damage_type,
+            counter: unit.counterattack,
+        }
+    }
+}
+
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Defense {
+    /// Percent taken off the hit chance of attacks against the unit.
+    pub evasion: i32,
+    pub armor: Armor,
+}
+
+impl From<&UnitJson> for Defense {
+    fn from(unit: &UnitJson) -> Defense {
+        Defense {
+            evasion: unit.evasion,
+            armor: Armor {
+                defense: unit.defense,
+                resistances: unit.resistances.clone(),
+                weaknesses: unit.weaknesses.clone(),
+            },
+        }
+    }
+}
+
+/// How many tiles around it a unit sees, see `fog`.
+#[derive(Component)]
+pub struct Vision {
+    pub radius: i32,
+}
+
+/// Copies reloaded stats onto a spawned unit, damage already taken is kept.
+pub fn apply_unit_stats(
+    unit: &UnitJson,
+    movement: &mut Movement,
+    health: &mut Health,
+    attack: &mut Attack,
+    defense: &mut Defense,
+    vision: &mut Vision,
+) {
+    movement.distance = unit.movement;
+    let damage_taken = health.max - health.value;
+    health.max = unit.health;
+    health.value = i32::max(unit.health - damage_taken, 1);
+    *attack = Attack::from(unit);
+    *defense = Defense::from(unit);
+    vision.radius = unit.vision;
+}
+
+/// Sprites of a unit, on the child entity that draws it.
+#[derive(Component)]
+pub struct UnitSprite {
+    pub idle: Handle<Image>,
+    pub attack: Handle<Image>,
 }
+
 #[derive(Default, Debug)]
 pub struct ActiveUnit {
     pub value: Option<Entity>,
@@ -41,87 +127,162 @@ pub struct SelectedUnit {
     pub value: Option<Entity>,
     pub grid: (i32, i32),
 }
+/// Tile clicked this frame, taken by whichever system acts on it.
+#[derive(Default, Debug)]
+pub struct TileClick {
+    pub tile: Option<(i32, i32)>,
+}
+/// Tile under the cursor, for previews.
+#[derive(Default, Debug)]
+pub struct TileHover {
+    pub tile: Option<(i32, i32)>,
+}
+/// Enemy picked in `SelectTarget`, attacked once the choice is confirmed.
+#[derive(Default, Debug)]
+pub struct AttackTarget {
+    pub tile: Option<(i32, i32)>,
+}
+/// Sent when an attack lands or misses, `tile` is where the target stood.
+#[derive(Debug, Clone, Copy)]
+pub struct DamageDealt {
+    pub tile: (i32, i32),
+    pub damage: i32,
+    pub hit: bool,
+    pub crit: bool,
+}
 #[derive(Default, Debug)]
 pub struct Spawners {
-    pub ai_locations: Vec<(f32, f32)>,
-    pub player_locations: Vec<(f32, f32)>,
-}
-
-fn get_mouse_position(
-    windows: Res<Windows>,
-    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-) -> Vec2 {
-    // assuming there is exactly one main camera entity, so query::single() is OK
-    let (camera, camera_transform) = q_camera.single();
-
-    // get the window that the camera is displaying to (or the primary window)
-    let wnd = if let RenderTarget::Window(id) = camera.target {
-        windows.get(id).unwrap()
-    } else {
-        windows.get_primary().unwrap()
-    };
-
-    // check if the cursor is inside the window and get its position
-    if let Some(screen_pos) = wnd.cursor_position() {
-        // get the size of the window
-        let window_size = Vec2::new(wnd.width() as f32, wnd.height() as f32);
-        // convert screen position [0..resolution] to ndc [-1..1] (gpu coordinates)
-        let ndc = (screen_pos / window_size) * 2.0 - Vec2::ONE;
-        // matrix for undoing the projection and camera transform
-        let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
-        // use it to convert ndc to world-space coordinates
-        let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
-        // reduce it to a 2D value
-        return world_pos.truncate();
+    pub ai_locations: Vec<(i32, i32)>,
+    pub player_locations: Vec<(i32, i32)>,
+    pub zones: BTreeMap<String, Vec<(i32, i32)>>,
+}
+
+/// Every unit on the board, for the systems that play by the rules of `sim::Battle`.
+#[derive(SystemParam)]
+pub struct BattleUnits<'w, 's> {
+    board: Res<'w, Board>,
+    units: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static mut Unit,
+            &'static GridPosition,
+            &'static Movement,
+            &'static Health,
+            &'static Attack,
+            &'static Defense,
+            &'static Vision,
+        ),
+    >,
+}
+
+impl<'w, 's> BattleUnits<'w, 's> {
+    /// The battle as it stands with `turn` to play, and the entity of each of its units.
+    pub fn battle(&self, turn: Team) -> (Battle, Vec<Entity>) {
+        let (entities, units) = self
+            .units
+            .iter()
+            .map(
+                |(entity, unit, grid, movement, health, attack, defense, vision)| {
+                    let sim_unit = SimUnit {
+                        team: unit.team,
+                        pos: (grid.x, grid.y),
+                        movement: movement.distance,
+                        health: health.value,
+                        vision: vision.radius,
+                        damage: attack.dmg,
+                        max_damage: attack.max_dmg,
+                        range: attack.range,
+                        accuracy: attack.accuracy,
+                        evasion: defense.evasion,
+                        crit: attack.crit,
+                        damage_type: attack.damage_type,
+                        armor: defense.armor.clone(),
+                        counterattack: attack.counter,
+                        has_moved: unit.has_moved,
+                        has_attacked: unit.has_attacked,
+                    };
+                    (entity, sim_unit)
+                },
+            )
+            .unzip();
+        let battle = Battle {
+            board: self.board.clone(),
+            units,
+            turn,
+        };
+        (battle, entities)
+    }
+
+    /// Copies what the units have done this turn back onto their entities, health is left to
+    /// the attack animation.
+    pub fn apply(&mut self, battle: &Battle, entities: &[Entity]) {
+        for (entity, sim_unit) in entities.iter().zip(battle.units.iter()) {
+            if let Ok((_e, mut unit, ..)) = self.units.get_mut(*entity) {
+                if unit.has_moved != sim_unit.has_moved
+                    || unit.has_attacked != sim_unit.has_attacked
+                {
+                    unit.has_moved = sim_unit.has_moved;
+                    unit.has_attacked = sim_unit.has_attacked;
+                }
+            }
+        }
     }
-    return Vec2::ZERO;
 }
 
 fn set_selected_unit(
     mut selected: ResMut<SelectedUnit>,
     mut active: ResMut<ActiveUnit>,
-    mut mouse_input: ResMut<Input<MouseButton>>,
-    windows: Res<Windows>,
-    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-    units: Query<(Entity, &Transform, &GridPosition, &Unit)>,
+    mut click: ResMut<TileClick>,
+    units: Query<(Entity, &GridPosition, &Unit)>,
     mut phase: ResMut<State<TurnPhase>>,
+    mut decisions: EventWriter<BattleCommand>,
+    vision: Res<TeamVision>,
 ) {
     if !(*phase.current() == TurnPhase::SelectMove || *phase.current() == TurnPhase::SelectTarget)
-        && mouse_input.just_pressed(MouseButton::Left)
+        && click.tile.is_some()
     {
-        let mouse_pos = get_mouse_position(windows, q_camera);
-        //get closest
-        let min_dist = 32.0;
-        // let mut selection: Option<&Label> = None;
-        if let Some((entity, _transform, grid, unit)) =
-            units
-                .into_iter()
-                .find(|(_entity, transform, _grid, _unit)| {
-                    mouse_pos.distance(Vec2::new(transform.translation.x, transform.translation.y))
-                        <= min_dist
-                })
-        {
+        // enemies in the fog cannot be picked
+        if let Some((entity, grid, unit)) = units.into_iter().find(|(_entity, grid, unit)| {
+            click.tile == Some((grid.x, grid.y))
+                && (unit.team == Team::PLAYER || vision.sees(Team::PLAYER, (grid.x, grid.y)))
+        }) {
             selected.value = entity.into();
             selected.grid = (grid.x, grid.y);
-            let cur_phase = *phase.current();
-            if !unit.has_acted && unit.team == Team::PLAYER {
-                if cur_phase == TurnPhase::SelectUnit {
-                    active.value = entity.into();
-                    phase.set(TurnPhase::SelectMove).unwrap();
-                } else if cur_phase == TurnPhase::SelectAttacker {
-                    active.value = entity.into();
+            if !unit.is_done()
+                && unit.team == Team::PLAYER
+                && *phase.current() == TurnPhase::SelectUnit
+            {
+                active.value = entity.into();
+                decisions.send(BattleCommand::Select {
+                    team: Team::PLAYER,
+                    unit: (grid.x, grid.y),
+                });
+                if unit.has_moved {
                     phase.set(TurnPhase::SelectTarget).unwrap();
+                } else {
+                    phase.set(TurnPhase::SelectMove).unwrap();
                 }
-                mouse_input.reset(MouseButton::Left);
+                click.tile = None;
             }
         }
     }
 }
 
+fn clear_tile_click(mut click: ResMut<TileClick>) {
+    click.tile = None;
+}
+
 impl Plugin for UnitsPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<SelectedUnit>()
             .init_resource::<Spawners>()
-            .add_system(set_selected_unit);
+            .init_resource::<TileClick>()
+            .init_resource::<TileHover>()
+            .init_resource::<AttackTarget>()
+            .add_event::<DamageDealt>()
+            .add_system(set_selected_unit)
+            .add_system_to_stage(CoreStage::Last, clear_tile_click);
     }
 }
@@ -0,0 +1,99 @@
+mod common;
+
+use bevy::prelude::*;
+use common::{Harness, PIRATE_1, PIRATE_2, PIRATE_3};
+use tbt::{
+    sim::{Affinity, Armor, DamageType, Odds},
+    units::{Attack, Defense},
+};
+
+fn armor(harness: &Harness, unit: Entity) -> Armor {
+    harness
+        .app
+        .world
+        .get::<Defense>(unit)
+        .unwrap()
+        .armor
+        .clone()
+}
+
+fn forecast_odds(harness: &mut Harness, from: (i32, i32), target: (i32, i32)) -> Odds {
+    harness.click_tile(from);
+    harness.press_key(KeyCode::Space);
+    harness.hover_tile(Some(target));
+    let odds = harness.forecast().unwrap().odds;
+    harness.press_key(KeyCode::Escape);
+    harness.press_key(KeyCode::Escape);
+    odds
+}
+
+#[test]
+fn resistances_and_weaknesses_apply_before_defense() {
+    let armor = Armor {
+        defense: 1,
+        resistances: vec![DamageType::Pierce],
+        weaknesses: vec![DamageType::Blunt],
+    };
+    assert_eq!(armor.mitigate(6, DamageType::Pierce), 2);
+    assert_eq!(armor.mitigate(6, DamageType::Blunt), 8);
+    assert_eq!(armor.mitigate(6, DamageType::Slash), 5);
+    // a hit always does something
+    assert_eq!(armor.mitigate(1, DamageType::Slash), 1);
+
+    let odds = Odds::new(90, 0, 0, 4, 6).against(&armor, DamageType::Pierce);
+    assert_eq!((odds.min_damage, odds.max_damage), (1, 2));
+    assert_eq!(odds.affinity, Affinity::Resistant);
+
+    let torn = Armor {
+        defense: 0,
+        resistances: vec![DamageType::Fire],
+        weaknesses: vec![DamageType::Fire],
+    };
+    assert_eq!(torn.affinity(DamageType::Fire), Affinity::Neutral);
+}
+
+#[test]
+fn skeletons_resist_pierce_and_zombies_burn() {
+    let mut harness = Harness::new();
+    let skelly = harness.skelly();
+    let zombie = harness.zombie();
+    harness.place(skelly, (4, 5));
+    harness.place(zombie, (6, 4));
+    harness.step();
+
+    // the fire pirate's 5 to 6 damage, half again on a zombie
+    let odds = forecast_odds(&mut harness, PIRATE_3, (6, 4));
+    assert_eq!(odds.affinity, Affinity::Weak);
+    assert_eq!((odds.min_damage, odds.max_damage), (7, 9));
+
+    // 7 to 9 pierce is halved, then the skelly's defense of 1 comes off
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    harness
+        .app
+        .world
+        .get_mut::<Attack>(pirate)
+        .unwrap()
+        .damage_type = DamageType::Pierce;
+    let odds = forecast_odds(&mut harness, PIRATE_1, (4, 5));
+    assert_eq!(odds.affinity, Affinity::Resistant);
+    assert_eq!((odds.min_damage, odds.max_damage), (2, 3));
+}
+
+#[test]
+fn attacks_and_counters_land_through_armor() {
+    let mut harness = Harness::new();
+    let pirate = harness.unit_at(PIRATE_2).unwrap();
+    let skelly = harness.skelly();
+    let (pirate_armor, skelly_armor) = (armor(&harness, pirate), armor(&harness, skelly));
+    harness.sure_hits();
+    harness.app.world.get_mut::<Defense>(pirate).unwrap().armor = pirate_armor;
+    harness.app.world.get_mut::<Defense>(skelly).unwrap().armor = skelly_armor;
+    harness.place(skelly, (3, 5));
+    let (pirate_health, skelly_health) = (harness.health(pirate), harness.health(skelly));
+
+    harness.attack(PIRATE_2, (3, 5));
+    // 3 blunt on a skelly is 4, less its defense of 1
+    assert_eq!(harness.health(skelly), skelly_health - 3);
+    // 2 pierce back, less the pirate's defense of 1
+    assert_eq!(harness.health(pirate), pirate_health - 1);
+}
@@ -0,0 +1,271 @@
+//! Headless app with the game plugins, driven by scripted clicks and key presses.
+// each test binary uses a different subset of the helpers
+#![allow(dead_code)]
+
+use bevy::{asset::AssetPlugin, core::CorePlugin, prelude::*};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+use tbt::{
+    ai_units::{Ai, AiUnitsPlugin, EnemyKind},
+    attacks::AttacksPlugin,
+    content::{ContentErrors, ContentPlugin},
+    fog::FogPlugin,
+    game_over::GameOverPlugin,
+    grid::{GridPlugin, GridPosition},
+    health_bars::HealthBarsPlugin,
+    level::{LevelHandles, LevelPlugin},
+    pathfinding::PathfindingPlugin,
+    player_units::{AttackForecast, Player, PlayerUnitsPlugin},
+    replay::{Playback, Replay, ReplayPlugin},
+    rng::{RngPlugin, SeedArg},
+    save::{SavePlugin, UserDir},
+    sim::{Armor, Forecast},
+    states::TurnPhase,
+    undo::UndoPlugin,
+    units::{Attack, Defense, Health, TileClick, TileHover, Unit, UnitsPlugin},
+};
+
+/// Simulated time per frame, units walk a tile in a handful of frames.
+const FRAME: Duration = Duration::from_millis(50);
+const MAX_FRAMES: usize = 2000;
+
+// 001.json deploys the pirates of the roster here, (4, 5) in front of pirate 1 is road
+
+/// Pirate 1: movement 1, range 1, damage 7 to 9.
+pub const PIRATE_1: (i32, i32) = (4, 4);
+/// Pirate 2: movement 5, range 1, damage 3.
+pub const PIRATE_2: (i32, i32) = (3, 4);
+/// Pirate 3: movement 3, range 4, damage 5 to 6.
+pub const PIRATE_3: (i32, i32) = (5, 4);
+
+static HARNESSES: AtomicUsize = AtomicUsize::new(0);
+
+/// Folder of its own for each harness, so tests running side by side keep their saves apart
+/// and none end up in the checkout.
+fn user_dir() -> PathBuf {
+    let n = HARNESSES.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("tbt-test-{}-{}", std::process::id(), n))
+}
+
+pub struct Harness {
+    pub app: App,
+    now: Instant,
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.app.world.resource::<UserDir>().0);
+    }
+}
+
+impl Harness {
+    /// Builds the app and runs it until the level is loaded and the player can act.
+    pub fn new() -> Harness {
+        Harness::start(None, Playback::default())
+    }
+
+    /// Same as `new`, with the battle seed pinned as if passed with `--seed`.
+    pub fn with_seed(seed: u64) -> Harness {
+        Harness::start(Some(seed), Playback::default())
+    }
+
+    /// Same as `new`, playing back a recorded battle as if passed with `--replay`.
+    pub fn replaying(replay: Replay) -> Harness {
+        Harness::start(Some(replay.seed), Playback::new(replay))
+    }
+
+    fn start(seed: Option<u64>, playback: Playback) -> Harness {
+        let mut app = App::new();
+        app.add_plugin(CorePlugin)
+            .add_plugin(AssetPlugin)
+            .insert_resource(Time::default())
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<Input<MouseButton>>()
+            .add_plugin(ContentPlugin)
+            .add_plugin(LevelPlugin)
+            .add_plugin(RngPlugin)
+            .add_plugin(GridPlugin)
+            .add_plugin(FogPlugin)
+            .add_plugin(UnitsPlugin)
+            .add_plugin(PlayerUnitsPlugin)
+            .add_plugin(AiUnitsPlugin)
+            .add_plugin(AttacksPlugin)
+            .add_plugin(PathfindingPlugin)
+            .add_plugin(HealthBarsPlugin)
+            .add_plugin(GameOverPlugin)
+            .add_plugin(SavePlugin)
+            .add_plugin(UndoPlugin)
+            .add_plugin(ReplayPlugin)
+            .insert_resource(SeedArg(seed))
+            .insert_resource(playback)
+            .insert_resource(UserDir(user_dir()))
+            .add_state(TurnPhase::LoadAssets);
+        let mut harness = Harness {
+            app,
+            now: Instant::now(),
+        };
+        // assets load on background threads
+        for _ in 0..MAX_FRAMES {
+            if harness.phase() == TurnPhase::SelectUnit {
+                return harness;
+            }
+            harness.step();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let errors = &harness.app.world.resource::<ContentErrors>().0;
+        panic!(
+            "level never loaded, stuck in {:?} {:?}",
+            harness.phase(),
+            errors
+        );
+    }
+
+    pub fn step(&mut self) {
+        self.now += FRAME;
+        let now = self.now;
+        self.app
+            .world
+            .resource_mut::<Time>()
+            .update_with_instant(now);
+        self.app.update();
+    }
+
+    /// Steps until `phase` is reached, panicking if it never is.
+    pub fn run_until(&mut self, phase: TurnPhase) {
+        for _ in 0..MAX_FRAMES {
+            if self.phase() == phase {
+                return;
+            }
+            self.step();
+        }
+        panic!("never reached {:?}, stuck in {:?}", phase, self.phase());
+    }
+
+    /// Steps until the phase is no longer `phase` and returns the new one.
+    pub fn run_while(&mut self, phase: TurnPhase) -> TurnPhase {
+        for _ in 0..MAX_FRAMES {
+            if self.phase() != phase {
+                return self.phase();
+            }
+            self.step();
+        }
+        panic!("stuck in {:?}", phase);
+    }
+
+    pub fn phase(&self) -> TurnPhase {
+        *self.app.world.resource::<State<TurnPhase>>().current()
+    }
+
+    pub fn click_tile(&mut self, tile: (i32, i32)) {
+        self.app.world.resource_mut::<TileClick>().tile = Some(tile);
+        self.step();
+    }
+
+    pub fn hover_tile(&mut self, tile: Option<(i32, i32)>) {
+        self.app.world.resource_mut::<TileHover>().tile = tile;
+        self.step();
+    }
+
+    pub fn forecast(&self) -> Option<Forecast> {
+        self.app.world.resource::<AttackForecast>().0
+    }
+
+    /// Picks the unit, stays put and clicks the target twice, then waits out the attack.
+    pub fn attack(&mut self, from: (i32, i32), target: (i32, i32)) {
+        self.click_tile(from);
+        self.press_key(KeyCode::Space);
+        self.click_tile(target);
+        self.click_tile(target);
+        self.run_until(TurnPhase::SelectUnit);
+    }
+
+    pub fn press_key(&mut self, key: KeyCode) {
+        self.app.world.resource_mut::<Input<KeyCode>>().press(key);
+        self.step();
+        let mut input = self.app.world.resource_mut::<Input<KeyCode>>();
+        input.release(key);
+        input.clear();
+    }
+
+    pub fn players(&mut self) -> Vec<Entity> {
+        let mut query = self.app.world.query_filtered::<Entity, With<Player>>();
+        query.iter(&self.app.world).collect()
+    }
+
+    pub fn ai_units(&mut self) -> Vec<Entity> {
+        let mut query = self.app.world.query_filtered::<Entity, With<Ai>>();
+        query.iter(&self.app.world).collect()
+    }
+
+    /// An ai unit spawned from the named enemy file.
+    pub fn enemy(&mut self, name: &str) -> Entity {
+        let kind = self.app.world.resource::<LevelHandles>().enemies[name].clone();
+        let mut query = self.app.world.query::<(Entity, &EnemyKind)>();
+        query
+            .iter(&self.app.world)
+            .find(|(_e, enemy)| enemy.0 == kind)
+            .map(|(e, _enemy)| e)
+            .unwrap_or_else(|| panic!("no {} on the board", name))
+    }
+
+    /// A skelly, which has 15 health and strikes back from up to three tiles away.
+    pub fn skelly(&mut self) -> Entity {
+        self.enemy("skelly")
+    }
+
+    /// A zombie, which has 5 health and never strikes back.
+    pub fn zombie(&mut self) -> Entity {
+        self.enemy("zombie")
+    }
+
+    pub fn unit_at(&mut self, tile: (i32, i32)) -> Option<Entity> {
+        let mut query = self.app.world.query::<(Entity, &GridPosition, &Unit)>();
+        query
+            .iter(&self.app.world)
+            .find(|(_e, grid, _unit)| (grid.x, grid.y) == tile)
+            .map(|(e, _grid, _unit)| e)
+    }
+
+    pub fn position(&self, unit: Entity) -> (i32, i32) {
+        let grid = self.app.world.get::<GridPosition>(unit).unwrap();
+        (grid.x, grid.y)
+    }
+
+    /// Moves a unit without walking, for setting up a scenario.
+    pub fn place(&mut self, unit: Entity, tile: (i32, i32)) {
+        let mut grid = self.app.world.get_mut::<GridPosition>(unit).unwrap();
+        grid.x = tile.0;
+        grid.y = tile.1;
+    }
+
+    pub fn health(&self, unit: Entity) -> i32 {
+        self.app.world.get::<Health>(unit).unwrap().value
+    }
+
+    pub fn unit(&self, unit: Entity) -> &Unit {
+        self.app.world.get::<Unit>(unit).unwrap()
+    }
+
+    /// Takes the dice and armor out of combat: every blow hits for the attacker's lowest
+    /// damage and none crit.
+    pub fn sure_hits(&mut self) {
+        let mut query = self.app.world.query::<(&mut Attack, &mut Defense)>();
+        for (mut attack, mut defense) in query.iter_mut(&mut self.app.world) {
+            attack.accuracy = 100;
+            attack.crit = 0;
+            attack.max_dmg = attack.dmg;
+            defense.evasion = 0;
+            defense.armor = Armor::default();
+        }
+    }
+
+    pub fn despawn(&mut self, units: Vec<Entity>) {
+        for unit in units {
+            despawn_with_children_recursive(&mut self.app.world, unit);
+        }
+    }
+}
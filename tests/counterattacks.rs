@@ -0,0 +1,108 @@
+mod common;
+
+use bevy::prelude::*;
+use common::{Harness, PIRATE_1, PIRATE_2, PIRATE_3};
+use tbt::{
+    rng::GameRng,
+    sim::{can_counter, roll_attack, Board, Odds},
+    states::TurnPhase,
+    units::Health,
+};
+
+#[test]
+fn a_surviving_defender_strikes_back() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    let skelly = harness.skelly();
+    harness.place(skelly, (4, 5));
+    let (pirate_health, skelly_health) = (harness.health(pirate), harness.health(skelly));
+
+    harness.attack(PIRATE_1, (4, 5));
+    assert_eq!(harness.health(skelly), skelly_health - 7);
+    assert_eq!(harness.health(pirate), pirate_health - 2);
+}
+
+#[test]
+fn defenders_only_strike_back_within_their_own_range() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    let pirate = harness.unit_at(PIRATE_3).unwrap();
+    harness.place(pirate, (4, 1));
+    let skelly = harness.skelly();
+    harness.place(skelly, (4, 5));
+    harness.step();
+    let (pirate_health, skelly_health) = (harness.health(pirate), harness.health(skelly));
+
+    // four tiles is in the pirate's range but not the skelly's
+    harness.attack((4, 1), (4, 5));
+    assert_eq!(harness.health(skelly), skelly_health - 5);
+    assert_eq!(harness.health(pirate), pirate_health);
+}
+
+#[test]
+fn units_can_opt_out_of_counterattacks() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    let pirate = harness.unit_at(PIRATE_2).unwrap();
+    let zombie = harness.zombie();
+    harness.place(zombie, (3, 5));
+    let (pirate_health, zombie_health) = (harness.health(pirate), harness.health(zombie));
+
+    harness.click_tile(PIRATE_2);
+    harness.press_key(KeyCode::Space);
+    harness.hover_tile(Some((3, 5)));
+    assert_eq!(harness.forecast().unwrap().counter, None);
+    harness.click_tile((3, 5));
+    harness.click_tile((3, 5));
+    harness.run_until(TurnPhase::SelectUnit);
+    assert_eq!(harness.health(zombie), zombie_health - 3);
+    assert_eq!(harness.health(pirate), pirate_health);
+}
+
+#[test]
+fn a_counter_can_defeat_the_attacker() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    harness.app.world.get_mut::<Health>(pirate).unwrap().value = 1;
+    let skelly = harness.skelly();
+    harness.place(skelly, (4, 5));
+
+    harness.click_tile(PIRATE_1);
+    harness.press_key(KeyCode::Space);
+    harness.hover_tile(Some((4, 5)));
+    let forecast = harness.forecast().unwrap();
+    assert!(forecast.counter.unwrap().killed);
+    assert_eq!(forecast.attacker_after(), -1);
+
+    harness.click_tile((4, 5));
+    harness.click_tile((4, 5));
+    harness.run_until(TurnPhase::SelectUnit);
+    assert!(harness.app.world.get_entity(pirate).is_none());
+    assert_eq!(harness.players().len(), 2);
+}
+
+#[test]
+fn counters_need_range_sight_and_a_survivor() {
+    let mut board = Board::default();
+    for x in 0..5 {
+        for y in 0..5 {
+            board.set_tile((x, y), false, 1);
+        }
+    }
+    board.set_obstacle((3, 2));
+    assert!(can_counter(&board, (2, 3), (2, 2), 1, true));
+    assert!(!can_counter(&board, (2, 3), (2, 2), 1, false));
+    assert!(!can_counter(&board, (4, 4), (2, 2), 1, true));
+    assert!(!can_counter(&board, (4, 2), (2, 2), 2, true));
+
+    let mut rng = GameRng::new(1);
+    let sure = Odds::new(100, 0, 0, 4, 4);
+    let (roll, counter) = roll_attack(sure, 10, Some(sure), &mut rng);
+    assert_eq!(roll.damage, 4);
+    assert_eq!(counter.unwrap().damage, 4);
+    // no counter from a defeated unit
+    let (_roll, counter) = roll_attack(sure, 3, Some(sure), &mut rng);
+    assert_eq!(counter, None);
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use bevy::prelude::*;
+use common::Harness;
+use tbt::{
+    fog::{Fog, Ghost, LastSeen},
+    grid::GridPosition,
+    replay::{BattleCommand, CommandLog},
+    sim::Team,
+    states::TurnPhase,
+    units::{SelectedUnit, Vision},
+};
+
+/// Narrows what every unit of the given side sees, the board is small enough to see it whole.
+fn set_vision(harness: &mut Harness, units: Vec<Entity>, radius: i32) {
+    for unit in units {
+        harness.app.world.get_mut::<Vision>(unit).unwrap().radius = radius;
+    }
+    harness.step();
+}
+
+fn is_visible(harness: &Harness, unit: Entity) -> bool {
+    harness
+        .app
+        .world
+        .get::<Visibility>(unit)
+        .unwrap()
+        .is_visible
+}
+
+fn fog_alpha(harness: &mut Harness, tile: (i32, i32)) -> f32 {
+    let mut fog = harness
+        .app
+        .world
+        .query_filtered::<(&Parent, &Sprite), With<Fog>>();
+    let (_parent, sprite) = fog
+        .iter(&harness.app.world)
+        .find(|(parent, _sprite)| {
+            let grid = harness.app.world.get::<GridPosition>(parent.get()).unwrap();
+            (grid.x, grid.y) == tile
+        })
+        .unwrap();
+    sprite.color.a()
+}
+
+fn ghosts_of(harness: &mut Harness, unit: Entity) -> Vec<(i32, i32)> {
+    let mut query = harness.app.world.query::<&Ghost>();
+    query
+        .iter(&harness.app.world)
+        .filter(|ghost| ghost.unit == unit)
+        .map(|ghost| ghost.tile)
+        .collect()
+}
+
+#[test]
+fn enemies_out_of_sight_are_hidden_in_the_fog() {
+    let mut harness = Harness::new();
+    let players = harness.players();
+    set_vision(&mut harness, players, 1);
+    let mut ai_units = harness.ai_units();
+    let near = ai_units.pop().unwrap();
+    let far = ai_units.pop().unwrap();
+    harness.place(near, (4, 5));
+    harness.place(far, (4, 8));
+    harness.step();
+
+    assert!(is_visible(&harness, near));
+    assert!(!is_visible(&harness, far));
+    assert_eq!(fog_alpha(&mut harness, (4, 5)), 0.0);
+    assert!(fog_alpha(&mut harness, (4, 8)) > 0.0);
+
+    // hidden enemies cannot be picked to look at
+    harness.click_tile((4, 8));
+    assert_eq!(harness.app.world.resource::<SelectedUnit>().value, None);
+    harness.click_tile((4, 5));
+    assert_eq!(
+        harness.app.world.resource::<SelectedUnit>().value,
+        Some(near)
+    );
+}
+
+#[test]
+fn a_ghost_stays_where_an_enemy_was_last_seen() {
+    let mut harness = Harness::new();
+    let players = harness.players();
+    set_vision(&mut harness, players.clone(), 2);
+    let enemy = harness.ai_units()[0];
+    harness.place(enemy, (4, 6));
+    harness.step();
+    assert!(is_visible(&harness, enemy));
+    assert!(ghosts_of(&mut harness, enemy).is_empty());
+
+    // it slips out of sight and walks off
+    set_vision(&mut harness, players.clone(), 1);
+    harness.place(enemy, (4, 8));
+    harness.step();
+    assert!(!is_visible(&harness, enemy));
+    assert_eq!(ghosts_of(&mut harness, enemy), vec![(4, 6)]);
+
+    // looking at the tile again shows it is empty
+    set_vision(&mut harness, players, 2);
+    harness.step();
+    assert!(ghosts_of(&mut harness, enemy).is_empty());
+    assert!(!is_visible(&harness, enemy));
+}
+
+#[test]
+fn ghosts_survive_a_save_and_load() {
+    let mut harness = Harness::new();
+    for (unit, tile) in harness.players().into_iter().zip([(0, 0), (1, 0), (0, 1)]) {
+        harness.place(unit, tile);
+    }
+    for (unit, tile) in harness
+        .ai_units()
+        .into_iter()
+        .zip([(7, 8), (8, 4), (8, 3), (8, 2)])
+    {
+        harness.place(unit, tile);
+    }
+    let enemy = harness.unit_at((7, 8)).unwrap();
+    // seen on the far corner before it stepped aside
+    harness
+        .app
+        .world
+        .resource_mut::<LastSeen>()
+        .0
+        .insert(enemy, (8, 8));
+    harness.step();
+    assert_eq!(ghosts_of(&mut harness, enemy), vec![(8, 8)]);
+
+    harness.press_key(KeyCode::F5);
+    harness.press_key(KeyCode::F9);
+    harness.run_until(TurnPhase::SelectUnit);
+    let enemy = harness.unit_at((7, 8)).unwrap();
+    assert_eq!(ghosts_of(&mut harness, enemy), vec![(8, 8)]);
+}
+
+#[test]
+fn the_ai_only_chases_what_it_sees() {
+    let mut harness = Harness::with_seed(3);
+    let ai_units = harness.ai_units();
+    set_vision(&mut harness, ai_units.clone(), 1);
+    let before: Vec<(i32, i32)> = ai_units.iter().map(|u| harness.position(*u)).collect();
+
+    // with nothing in sight every ai unit waits, the turn can pass within a frame
+    harness.press_key(KeyCode::Space);
+    for _ in 0..10 {
+        harness.step();
+    }
+    harness.run_until(TurnPhase::SelectUnit);
+    let commands = &harness.app.world.resource::<CommandLog>().replay.commands;
+    let end_turn = commands
+        .iter()
+        .position(|command| *command == BattleCommand::EndTurn)
+        .unwrap();
+    assert!(commands[end_turn..]
+        .iter()
+        .any(|command| matches!(command, BattleCommand::Wait { team: Team::AI })));
+    let after: Vec<(i32, i32)> = ai_units.iter().map(|u| harness.position(*u)).collect();
+    assert_eq!(before, after);
+}
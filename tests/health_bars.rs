@@ -0,0 +1,64 @@
+mod common;
+
+use bevy::prelude::*;
+use common::{Harness, PIRATE_1};
+use tbt::health_bars::{DamageNumber, HealthBar, BAR_WIDTH};
+
+fn bar_width(harness: &Harness, unit: Entity) -> f32 {
+    let children = harness.app.world.get::<Children>(unit).unwrap();
+    let bar = children
+        .iter()
+        .find(|child| harness.app.world.get::<HealthBar>(**child).is_some())
+        .expect("unit has no health bar");
+    let sprite = harness.app.world.get::<Sprite>(*bar).unwrap();
+    sprite.custom_size.unwrap().x
+}
+
+fn damage_numbers(harness: &mut Harness) -> Vec<String> {
+    let mut query = harness
+        .app
+        .world
+        .query_filtered::<&Text, With<DamageNumber>>();
+    query
+        .iter(&harness.app.world)
+        .map(|text| text.sections[0].value.clone())
+        .collect()
+}
+
+#[test]
+fn every_unit_gets_a_full_health_bar() {
+    let mut harness = Harness::new();
+    // bars are added the frame after the units spawn
+    harness.step();
+    let mut units = harness.players();
+    units.extend(harness.ai_units());
+    for unit in units {
+        assert_eq!(bar_width(&harness, unit), BAR_WIDTH);
+    }
+}
+
+#[test]
+fn hits_shrink_the_bar_and_float_a_number() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    // the skelly survives a hit from the pirate
+    let target = harness.skelly();
+    harness.place(target, (4, 5));
+    let health = harness.health(target);
+
+    harness.attack(PIRATE_1, (4, 5));
+    assert_eq!(harness.health(target), health - 7);
+    let expected = BAR_WIDTH * (health - 7) as f32 / health as f32;
+    assert!((bar_width(&harness, target) - expected).abs() < 0.01);
+    // one number for the hit, one for the skelly striking back
+    assert_eq!(
+        damage_numbers(&mut harness),
+        vec!["-7".to_string(), "-2".to_string()]
+    );
+
+    // the number fades out after a second
+    for _ in 0..30 {
+        harness.step();
+    }
+    assert!(damage_numbers(&mut harness).is_empty());
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use bevy::prelude::*;
+use common::{Harness, PIRATE_1};
+use tbt::{
+    health_bars::DamageNumber,
+    rng::GameRng,
+    sim::{choose_ai_target, AiTarget, Board, Odds, CRIT_MULTIPLIER},
+    units::Attack,
+};
+
+#[test]
+fn evasion_lowers_the_hit_chance() {
+    let odds = Odds::new(85, 10, 20, 4, 6);
+    assert_eq!(odds.hit, 75);
+    assert_eq!(odds.crit, 20);
+    // three quarters of the hits, for five on average, a fifth of them doubled
+    assert!((odds.expected_damage() - 4.5).abs() < 0.001);
+    assert_eq!(Odds::new(30, 50, 0, 4, 4).hit, 0);
+    assert_eq!(Odds::new(130, 0, 0, 4, 4).hit, 100);
+}
+
+#[test]
+fn rolls_stay_within_the_odds() {
+    let mut rng = GameRng::new(3);
+    let sure = Odds::new(100, 0, 0, 3, 5);
+    let never = Odds::new(40, 40, 0, 3, 5);
+    let crits = Odds::new(100, 0, 100, 3, 3);
+    let mut damages = Vec::new();
+    for _ in 0..200 {
+        let roll = sure.roll(&mut rng);
+        assert!(roll.hit && !roll.crit);
+        damages.push(roll.damage);
+        let roll = never.roll(&mut rng);
+        assert!(!roll.hit);
+        assert_eq!(roll.damage, 0);
+        let roll = crits.roll(&mut rng);
+        assert!(roll.crit);
+        assert_eq!(roll.damage, 3 * CRIT_MULTIPLIER);
+    }
+    assert!(damages.iter().all(|damage| (3..=5).contains(damage)));
+    assert!(damages.contains(&3) && damages.contains(&5));
+}
+
+#[test]
+fn the_ai_goes_for_the_likeliest_kill() {
+    let mut board = Board::default();
+    for x in 0..5 {
+        for y in 0..5 {
+            board.set_tile((x, y), false, 1);
+        }
+    }
+    let target = |pos, health, odds| AiTarget { pos, health, odds };
+    let targets = [
+        // a sure hit that barely scratches a healthy unit
+        target((2, 3), 20, Odds::new(100, 0, 0, 4, 4)),
+        // a coin flip that would finish a wounded one
+        target((3, 2), 3, Odds::new(50, 0, 0, 4, 4)),
+        // out of reach
+        target((4, 4), 1, Odds::new(100, 0, 0, 4, 4)),
+    ];
+    assert_eq!(choose_ai_target(&board, (2, 2), 1, &targets), Some(1));
+}
+
+#[test]
+fn the_forecast_shows_the_odds() {
+    let mut harness = Harness::new();
+    let skelly = harness.skelly();
+    harness.place(skelly, (4, 5));
+
+    harness.click_tile(PIRATE_1);
+    harness.press_key(KeyCode::Space);
+    harness.hover_tile(Some((4, 5)));
+    let forecast = harness.forecast().unwrap();
+    // the pirate's accuracy less the skelly's evasion
+    assert_eq!(forecast.odds.hit, 75);
+    assert_eq!(forecast.odds.crit, 10);
+    assert_eq!(forecast.counter_odds.unwrap().hit, 70);
+}
+
+#[test]
+fn a_miss_deals_no_damage() {
+    let mut harness = Harness::new();
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    harness
+        .app
+        .world
+        .get_mut::<Attack>(pirate)
+        .unwrap()
+        .accuracy = 0;
+    let skelly = harness.skelly();
+    harness.place(skelly, (4, 5));
+    let health = harness.health(skelly);
+
+    harness.attack(PIRATE_1, (4, 5));
+    assert_eq!(harness.health(skelly), health);
+    let mut numbers = harness
+        .app
+        .world
+        .query_filtered::<&Text, With<DamageNumber>>();
+    assert!(numbers
+        .iter(&harness.app.world)
+        .any(|text| text.sections[0].value == "Miss"));
+}
@@ -0,0 +1,53 @@
+use tbt::sim::{choose_ai_target, AiTarget, Board, Odds};
+
+/// Open 9x9 board with obstacles on the given tiles.
+fn board(obstacles: &[(i32, i32)]) -> Board {
+    let mut board = Board::default();
+    for x in 0..9 {
+        for y in 0..9 {
+            let obstacle = obstacles.contains(&(x, y));
+            board.set_tile((x, y), obstacle, 1);
+            if obstacle {
+                board.set_obstacle((x, y));
+            }
+        }
+    }
+    board
+}
+
+#[test]
+fn obstacles_block_sight_both_ways() {
+    let board = board(&[(3, 3)]);
+    assert!(!board.line_of_sight((1, 1), (5, 5)));
+    assert!(!board.line_of_sight((5, 5), (1, 1)));
+    assert!(!board.line_of_sight((3, 0), (3, 6)));
+    assert!(board.line_of_sight((1, 1), (5, 4)));
+    assert!(board.line_of_sight((5, 4), (1, 1)));
+    // an obstacle right next to the line does not block it
+    assert!(board.line_of_sight((0, 2), (6, 2)));
+}
+
+#[test]
+fn units_do_not_block_sight() {
+    let mut board = board(&[]);
+    board.set_blocked((3, 3), true);
+    assert!(board.line_of_sight((1, 1), (5, 5)));
+}
+
+#[test]
+fn attacks_need_range_and_sight() {
+    let board = board(&[(4, 2)]);
+    assert!(board.can_attack((2, 2), (4, 4), 2));
+    assert!(!board.can_attack((2, 2), (5, 5), 2));
+    assert!(!board.can_attack((2, 2), (5, 2), 3));
+    // the skelly skips the hidden target for the one it can see
+    let targets: Vec<AiTarget> = [(6, 2), (2, 5)]
+        .into_iter()
+        .map(|pos| AiTarget {
+            pos,
+            health: 10,
+            odds: Odds::new(100, 0, 0, 2, 2),
+        })
+        .collect();
+    assert_eq!(choose_ai_target(&board, (2, 2), 4, &targets), Some(1));
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use bevy::prelude::*;
+use common::Harness;
+use tbt::{
+    replay::{BattleCommand, CommandLog, Playback, Replay},
+    sim::Team,
+    states::TurnPhase,
+};
+
+/// Positions and health of every unit, in a stable order.
+fn snapshot(harness: &mut Harness) -> Vec<((i32, i32), i32)> {
+    let mut units: Vec<((i32, i32), i32)> = harness
+        .players()
+        .into_iter()
+        .chain(harness.ai_units())
+        .map(|unit| (harness.position(unit), harness.health(unit)))
+        .collect();
+    units.sort();
+    units
+}
+
+/// Plays a move, a wait and a full ai turn, then returns the recording.
+fn record_battle(harness: &mut Harness) -> Replay {
+    harness.click_tile((4, 4));
+    harness.click_tile((4, 5));
+    harness.run_until(TurnPhase::SelectTarget);
+    harness.press_key(KeyCode::Space);
+    harness.click_tile((3, 4));
+    harness.press_key(KeyCode::Escape);
+    harness.press_key(KeyCode::Space);
+    harness.run_while(TurnPhase::SelectUnit);
+    harness.run_until(TurnPhase::SelectUnit);
+    harness.app.world.resource::<CommandLog>().replay.clone()
+}
+
+fn run_replay(harness: &mut Harness) {
+    for _ in 0..2000 {
+        if !harness.app.world.resource::<Playback>().is_playing() {
+            return;
+        }
+        harness.step();
+    }
+    panic!("replay never finished");
+}
+
+#[test]
+fn battles_are_recorded_with_their_seed() {
+    let mut harness = Harness::with_seed(11);
+    let replay = record_battle(&mut harness);
+    assert_eq!(replay.seed, 11);
+    // select, move, wait, select, back, end turn, then the ai
+    assert!(replay.commands.len() > 6);
+}
+
+#[test]
+fn a_replay_ends_in_the_recorded_state() {
+    let mut recorded = Harness::with_seed(5);
+    let replay = record_battle(&mut recorded);
+    let expected = snapshot(&mut recorded);
+
+    let mut replayed = Harness::replaying(replay.clone());
+    run_replay(&mut replayed);
+    assert_eq!(snapshot(&mut replayed), expected);
+    assert_eq!(
+        replayed.app.world.resource::<CommandLog>().replay,
+        replay,
+        "replay diverged"
+    );
+}
+
+#[test]
+fn the_player_cannot_interfere_with_a_replay() {
+    let mut recorded = Harness::with_seed(5);
+    let replay = record_battle(&mut recorded);
+    let expected = snapshot(&mut recorded);
+
+    let mut replayed = Harness::replaying(replay.clone());
+    for key in [KeyCode::Space, KeyCode::U, KeyCode::F10] {
+        replayed.press_key(key);
+    }
+    assert_eq!(replayed.phase(), TurnPhase::SelectUnit);
+    run_replay(&mut replayed);
+    assert_eq!(snapshot(&mut replayed), expected);
+    assert_eq!(
+        replayed.app.world.resource::<CommandLog>().replay,
+        replay,
+        "replay diverged"
+    );
+}
+
+#[test]
+fn a_paused_replay_only_moves_on_step() {
+    let mut recorded = Harness::with_seed(5);
+    let replay = record_battle(&mut recorded);
+    let mut replayed = Harness::replaying(replay);
+
+    replayed.press_key(KeyCode::P);
+    for _ in 0..40 {
+        replayed.step();
+    }
+    assert_eq!(
+        replayed
+            .app
+            .world
+            .resource::<CommandLog>()
+            .replay
+            .commands
+            .len(),
+        0
+    );
+    assert_eq!(replayed.phase(), TurnPhase::SelectUnit);
+
+    replayed.press_key(KeyCode::N);
+    for _ in 0..40 {
+        replayed.step();
+    }
+    assert_eq!(
+        replayed
+            .app
+            .world
+            .resource::<CommandLog>()
+            .replay
+            .commands
+            .len(),
+        1
+    );
+    assert_eq!(replayed.phase(), TurnPhase::SelectMove);
+}
+
+#[test]
+fn a_replay_that_diverges_hands_back_control() {
+    let mut recorded = Harness::with_seed(5);
+    let mut replay = record_battle(&mut recorded);
+    // the pirate on (4, 4) cannot reach this tile
+    replay.commands[1] = BattleCommand::Move {
+        team: Team::PLAYER,
+        to: (4, 8),
+    };
+    let mut replayed = Harness::replaying(replay);
+    run_replay(&mut replayed);
+    assert_eq!(replayed.phase(), TurnPhase::SelectMove);
+    let pirate = replayed.unit_at((4, 4)).unwrap();
+    assert!(!replayed.unit(pirate).has_moved);
+}
+
+/// Puts the first ai unit next to the pirate on (4, 4), the same one in every run.
+fn stage_target(harness: &mut Harness) {
+    let mut ai_units = harness.ai_units();
+    ai_units.sort_by_key(|unit| harness.position(*unit));
+    harness.place(ai_units[0], (4, 5));
+}
+
+#[test]
+fn replays_repeat_confirmed_attacks() {
+    let mut recorded = Harness::with_seed(5);
+    stage_target(&mut recorded);
+    recorded.attack((4, 4), (4, 5));
+    let replay = recorded.app.world.resource::<CommandLog>().replay.clone();
+    assert!(matches!(
+        replay.commands.last(),
+        Some(BattleCommand::Attack { .. })
+    ));
+    let expected = snapshot(&mut recorded);
+
+    let mut replayed = Harness::replaying(replay.clone());
+    stage_target(&mut replayed);
+    run_replay(&mut replayed);
+    replayed.run_until(TurnPhase::SelectUnit);
+    assert_eq!(snapshot(&mut replayed), expected);
+    assert_eq!(replayed.app.world.resource::<CommandLog>().replay, replay);
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use bevy::prelude::*;
+use common::Harness;
+use tbt::{
+    save::{UserDir, AUTOSAVE_PATH},
+    states::TurnPhase,
+};
+
+#[test]
+fn loading_a_quicksave_restores_the_battle() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    let pirate = harness.unit_at((4, 4)).unwrap();
+    let target = harness.ai_units()[0];
+    harness.place(target, (4, 5));
+    let health = harness.health(target);
+    harness.attack((4, 4), (4, 5));
+    assert!(harness.unit(pirate).is_done());
+
+    harness.press_key(KeyCode::F5);
+    harness.despawn(vec![target]);
+    harness.press_key(KeyCode::F9);
+    harness.run_until(TurnPhase::SelectUnit);
+
+    assert_eq!(harness.players().len(), 3);
+    assert_eq!(harness.ai_units().len(), 4);
+    let pirate = harness.unit_at((4, 4)).unwrap();
+    assert!(harness.unit(pirate).is_done());
+    let target = harness.unit_at((4, 5)).unwrap();
+    assert_eq!(harness.health(target), health - 7);
+    assert!(!harness.unit(target).is_done());
+}
+
+#[test]
+fn loading_the_autosave_goes_back_to_the_turn_start() {
+    let mut harness = Harness::new();
+    let autosave = harness.app.world.resource::<UserDir>().file(AUTOSAVE_PATH);
+    assert!(autosave.exists());
+    harness.click_tile((4, 4));
+    harness.click_tile((4, 5));
+    harness.run_until(TurnPhase::SelectTarget);
+    harness.press_key(KeyCode::Space);
+    assert_eq!(harness.phase(), TurnPhase::SelectUnit);
+
+    harness.press_key(KeyCode::F10);
+    harness.run_until(TurnPhase::SelectUnit);
+    assert_eq!(harness.unit_at((4, 5)), None);
+    let pirate = harness.unit_at((4, 4)).unwrap();
+    assert!(!harness.unit(pirate).is_done());
+}
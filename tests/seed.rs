@@ -0,0 +1,43 @@
+mod common;
+
+use common::Harness;
+use tbt::{cli::Args, rng::GameRng};
+
+fn ai_spawns(harness: &mut Harness) -> Vec<(i32, i32)> {
+    let mut spawns: Vec<(i32, i32)> = harness
+        .ai_units()
+        .into_iter()
+        .map(|unit| harness.position(unit))
+        .collect();
+    spawns.sort();
+    spawns
+}
+
+#[test]
+fn the_same_seed_replays_the_same_spawns() {
+    let mut first = Harness::with_seed(7);
+    let mut second = Harness::with_seed(7);
+    assert_eq!(first.app.world.resource::<GameRng>().seed(), 7);
+    assert_eq!(ai_spawns(&mut first), ai_spawns(&mut second));
+}
+
+#[test]
+fn different_seeds_roll_differently() {
+    let mut spawns: Vec<Vec<(i32, i32)>> = (0..4)
+        .map(|seed| ai_spawns(&mut Harness::with_seed(seed)))
+        .collect();
+    spawns.dedup();
+    assert!(spawns.len() > 1);
+}
+
+#[test]
+fn seed_and_replay_are_read_from_the_command_line() {
+    let parse = |list: &[&str]| Args::parse(list.iter().map(|arg| arg.to_string()));
+    assert_eq!(parse(&["--seed", "42"]).seed, Some(42));
+    assert_eq!(parse(&["--seed=9"]).seed, Some(9));
+    assert_eq!(parse(&["--seed", "many"]).seed, None);
+    assert_eq!(parse(&[]), Args::default());
+    let args = parse(&["--replay", "bug.json", "--seed", "3"]);
+    assert_eq!(args.replay.as_deref(), Some("bug.json"));
+    assert_eq!(args.seed, Some(3));
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use bevy::prelude::*;
+use common::Harness;
+use std::collections::HashSet;
+use tbt::{
+    ai_units::{DelayedSpawn, DelayedSpawns},
+    states::TurnPhase,
+    units::Spawners,
+};
+
+fn positions(harness: &mut Harness, units: Vec<Entity>) -> Vec<(i32, i32)> {
+    units
+        .into_iter()
+        .map(|unit| harness.position(unit))
+        .collect()
+}
+
+fn assert_no_stacking(harness: &mut Harness) {
+    let players = harness.players();
+    let ai_units = harness.ai_units();
+    let mut tiles = positions(harness, players);
+    tiles.extend(positions(harness, ai_units));
+    let unique: HashSet<&(i32, i32)> = tiles.iter().collect();
+    assert_eq!(unique.len(), tiles.len(), "units share a tile: {:?}", tiles);
+}
+
+/// Ends the player turn and lets the ai play its own.
+fn pass_turn(harness: &mut Harness) {
+    harness.press_key(KeyCode::Space);
+    harness.run_while(TurnPhase::SelectUnit);
+    harness.run_until(TurnPhase::SelectUnit);
+}
+
+#[test]
+fn waves_spawn_on_free_tiles_of_their_zone() {
+    for seed in 0..8 {
+        let mut harness = Harness::with_seed(seed);
+        assert_no_stacking(&mut harness);
+        let ai_units = harness.ai_units();
+        let mut rows: Vec<i32> = positions(&mut harness, ai_units)
+            .into_iter()
+            .map(|(_x, y)| y)
+            .collect();
+        rows.sort();
+        // 001.json sends its skellies north and its zombies south
+        assert_eq!(rows, vec![0, 0, 8, 8]);
+    }
+}
+
+#[test]
+fn a_full_zone_redirects_to_any_free_spawn() {
+    let mut harness = Harness::with_seed(1);
+    {
+        let mut spawners = harness.app.world.resource_mut::<Spawners>();
+        // both tiles hold player units
+        spawners.zones.insert(String::from("north"), vec![(4, 4)]);
+        spawners.ai_locations = vec![(3, 4), (1, 1)];
+    }
+    harness
+        .app
+        .world
+        .resource_mut::<DelayedSpawns>()
+        .0
+        .push(DelayedSpawn {
+            unit: String::from("skelly"),
+            zone: Some(String::from("north")),
+        });
+    let before = harness.ai_units().len();
+    pass_turn(&mut harness);
+
+    assert!(harness.app.world.resource::<DelayedSpawns>().0.is_empty());
+    assert_eq!(harness.ai_units().len(), before + 1);
+    assert_no_stacking(&mut harness);
+}
+
+#[test]
+fn units_wait_while_every_spawn_is_taken() {
+    let mut harness = Harness::with_seed(1);
+    {
+        let mut spawners = harness.app.world.resource_mut::<Spawners>();
+        spawners.zones.insert(String::from("north"), vec![(4, 4)]);
+        spawners.ai_locations = vec![(3, 4), (5, 4)];
+    }
+    harness
+        .app
+        .world
+        .resource_mut::<DelayedSpawns>()
+        .0
+        .push(DelayedSpawn {
+            unit: String::from("skelly"),
+            zone: Some(String::from("north")),
+        });
+    let before = harness.ai_units().len();
+    pass_turn(&mut harness);
+
+    assert_eq!(harness.app.world.resource::<DelayedSpawns>().0.len(), 1);
+    assert_eq!(harness.ai_units().len(), before);
+}
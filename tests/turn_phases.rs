@@ -0,0 +1,330 @@
+mod common;
+
+use bevy::prelude::*;
+use common::{Harness, PIRATE_1, PIRATE_2};
+use tbt::{
+    grid::{GridPosition, Tile},
+    replay::{BattleCommand, CommandLog},
+    sim::Team,
+    states::TurnPhase,
+    units::{Health, UnitSprite},
+};
+
+#[test]
+fn level_loads_into_select_unit() {
+    let mut harness = Harness::new();
+    let mut spawns: Vec<(i32, i32)> = harness
+        .players()
+        .into_iter()
+        .map(|unit| harness.position(unit))
+        .collect();
+    spawns.sort();
+    assert_eq!(spawns, vec![(3, 4), (4, 4), (5, 4)]);
+    assert_eq!(harness.ai_units().len(), 4);
+}
+
+#[test]
+fn clicking_a_player_unit_then_escape() {
+    let mut harness = Harness::new();
+    harness.click_tile(PIRATE_1);
+    assert_eq!(harness.phase(), TurnPhase::SelectMove);
+    harness.press_key(KeyCode::Escape);
+    assert_eq!(harness.phase(), TurnPhase::SelectUnit);
+}
+
+#[test]
+fn clicking_an_empty_tile_selects_nothing() {
+    let mut harness = Harness::new();
+    harness.click_tile((4, 2));
+    assert_eq!(harness.phase(), TurnPhase::SelectUnit);
+}
+
+#[test]
+fn moving_walks_the_unit_and_asks_for_a_target() {
+    let mut harness = Harness::new();
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    harness.click_tile(PIRATE_1);
+    harness.click_tile((4, 5));
+    assert_eq!(harness.phase(), TurnPhase::DoMove);
+    harness.run_until(TurnPhase::SelectTarget);
+    assert_eq!(harness.position(pirate), (4, 5));
+    assert!(harness.unit(pirate).has_moved);
+    assert!(!harness.unit(pirate).has_attacked);
+}
+
+#[test]
+fn tiles_beyond_the_movement_budget_are_ignored() {
+    let mut harness = Harness::new();
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    harness.click_tile(PIRATE_1);
+    harness.click_tile((4, 7));
+    assert_eq!(harness.phase(), TurnPhase::SelectMove);
+    assert_eq!(harness.position(pirate), PIRATE_1);
+}
+
+#[test]
+fn space_stays_then_waits() {
+    let mut harness = Harness::new();
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    harness.click_tile(PIRATE_1);
+    harness.press_key(KeyCode::Space);
+    assert_eq!(harness.phase(), TurnPhase::SelectTarget);
+    harness.press_key(KeyCode::Space);
+    assert_eq!(harness.phase(), TurnPhase::SelectUnit);
+    assert!(harness.unit(pirate).is_done());
+
+    // a finished unit cannot be activated again
+    harness.click_tile(PIRATE_1);
+    assert_eq!(harness.phase(), TurnPhase::SelectUnit);
+}
+
+#[test]
+fn a_vanished_active_unit_hands_back_unit_selection() {
+    let mut harness = Harness::new();
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    harness.click_tile(PIRATE_1);
+    harness.press_key(KeyCode::Space);
+    assert_eq!(harness.phase(), TurnPhase::SelectTarget);
+    harness.despawn(vec![pirate]);
+    harness.step();
+    assert_eq!(harness.phase(), TurnPhase::SelectUnit);
+
+    let pirate = harness.unit_at(PIRATE_2).unwrap();
+    harness.click_tile(PIRATE_2);
+    harness.despawn(vec![pirate]);
+    harness.press_key(KeyCode::Space);
+    assert_eq!(harness.phase(), TurnPhase::SelectUnit);
+}
+
+#[test]
+fn attacking_damages_the_target() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    let target = harness.ai_units()[0];
+    harness.place(target, (4, 5));
+    let health = harness.health(target);
+
+    harness.click_tile(PIRATE_1);
+    harness.press_key(KeyCode::Space);
+    // the first click only picks the target
+    harness.click_tile((4, 5));
+    assert_eq!(harness.phase(), TurnPhase::SelectTarget);
+    assert_eq!(harness.health(target), health);
+    harness.click_tile((4, 5));
+    // the damage lands once the attack animation reaches the target
+    assert_eq!(harness.phase(), TurnPhase::DoAttack);
+    assert_eq!(harness.health(target), health);
+    harness.run_until(TurnPhase::SelectUnit);
+    assert_eq!(harness.health(target), health - 7);
+    assert!(harness.unit(pirate).is_done());
+}
+
+/// Sprite file the unit is drawn with, it swaps to the attack sprite while attacking.
+fn sprite_path(harness: &Harness, unit: Entity) -> String {
+    let children = harness.app.world.get::<Children>(unit).unwrap();
+    let texture = children
+        .iter()
+        .find_map(|child| {
+            harness.app.world.get::<UnitSprite>(*child)?;
+            harness.app.world.get::<Handle<Image>>(*child)
+        })
+        .unwrap();
+    let path = harness
+        .app
+        .world
+        .resource::<AssetServer>()
+        .get_handle_path(texture)
+        .unwrap();
+    path.path().to_string_lossy().into_owned()
+}
+
+#[test]
+fn attacks_animate_and_defeated_units_fade_out() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    let target = harness.ai_units()[0];
+    harness.place(target, (4, 5));
+    harness.app.world.get_mut::<Health>(target).unwrap().value = 5;
+    assert_eq!(sprite_path(&harness, pirate), "sprites/pirate_1.png");
+
+    harness.click_tile(PIRATE_1);
+    harness.press_key(KeyCode::Space);
+    harness.click_tile((4, 5));
+    harness.click_tile((4, 5));
+    harness.step();
+    assert_eq!(harness.phase(), TurnPhase::DoAttack);
+    assert_eq!(sprite_path(&harness, pirate), "sprites/pirate_1_attack.png");
+
+    // the target is hit but stays around while it fades
+    while harness.health(target) > 0 {
+        harness.step();
+    }
+    assert_eq!(harness.phase(), TurnPhase::DoAttack);
+    assert_eq!(harness.ai_units().len(), 4);
+    harness.run_until(TurnPhase::SelectUnit);
+    assert_eq!(harness.ai_units().len(), 3);
+    assert_eq!(sprite_path(&harness, pirate), "sprites/pirate_1.png");
+}
+
+#[test]
+fn enter_confirms_the_picked_target() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    let target = harness.ai_units()[0];
+    harness.place(target, (4, 5));
+    let health = harness.health(target);
+
+    harness.click_tile(PIRATE_1);
+    harness.press_key(KeyCode::Space);
+    harness.press_key(KeyCode::Return);
+    assert_eq!(harness.phase(), TurnPhase::SelectTarget);
+    harness.click_tile((4, 5));
+    harness.press_key(KeyCode::Return);
+    assert_eq!(harness.phase(), TurnPhase::DoAttack);
+    harness.run_until(TurnPhase::SelectUnit);
+    assert_eq!(harness.health(target), health - 7);
+}
+
+#[test]
+fn hovering_a_target_forecasts_the_attack() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    let target = harness.ai_units()[0];
+    harness.place(target, (4, 5));
+    let health = harness.health(target);
+
+    harness.click_tile(PIRATE_1);
+    harness.press_key(KeyCode::Space);
+    assert_eq!(harness.forecast(), None);
+    harness.hover_tile(Some((4, 5)));
+    let forecast = harness.forecast().unwrap();
+    assert_eq!(forecast.attacker_health, harness.health(pirate));
+    assert_eq!(forecast.target_health, health);
+    assert_eq!(forecast.attack.damage, 7);
+    assert_eq!(forecast.attack.remaining, health - 7);
+    assert_eq!(forecast.attack.killed, health <= 7);
+    // the target survives and has the pirate in range, so it strikes back
+    let counter = forecast.counter.unwrap();
+    assert_eq!(
+        forecast.attacker_after(),
+        harness.health(pirate) - counter.damage
+    );
+
+    // tiles out of range or without an enemy forecast nothing
+    harness.hover_tile(Some((4, 2)));
+    assert_eq!(harness.forecast(), None);
+    harness.press_key(KeyCode::Escape);
+    harness.hover_tile(Some((4, 5)));
+    assert_eq!(harness.forecast(), None);
+}
+
+#[test]
+fn targets_out_of_range_cannot_be_attacked() {
+    let mut harness = Harness::new();
+    let target = harness.ai_units()[0];
+    harness.place(target, (4, 6));
+    let health = harness.health(target);
+
+    harness.click_tile(PIRATE_1);
+    harness.press_key(KeyCode::Space);
+    harness.click_tile((4, 6));
+    assert_eq!(harness.phase(), TurnPhase::SelectTarget);
+    assert_eq!(harness.health(target), health);
+}
+
+#[test]
+fn ending_the_turn_plays_the_ai_and_hands_back_control() {
+    let mut harness = Harness::new();
+    harness.press_key(KeyCode::Space);
+    // several phases, or the whole ai turn when it sees no one, can pass within one frame,
+    // so look for its decisions rather than its phases
+    let ai_acted = |harness: &Harness| {
+        let commands = &harness.app.world.resource::<CommandLog>().replay.commands;
+        commands
+            .iter()
+            .skip_while(|command| **command != BattleCommand::EndTurn)
+            .any(|command| {
+                matches!(
+                    command,
+                    BattleCommand::Select { team: Team::AI, .. }
+                        | BattleCommand::Wait { team: Team::AI }
+                )
+            })
+    };
+    for _ in 0..100 {
+        if ai_acted(&harness) {
+            break;
+        }
+        harness.step();
+    }
+    assert!(ai_acted(&harness));
+    harness.run_until(TurnPhase::SelectUnit);
+    for unit in harness.players() {
+        assert!(!harness.unit(unit).is_done());
+    }
+    for unit in harness.ai_units() {
+        assert!(!harness.unit(unit).is_done());
+    }
+}
+
+#[test]
+fn losing_every_player_unit_is_defeat() {
+    let mut harness = Harness::new();
+    let players = harness.players();
+    harness.despawn(players);
+    harness.run_until(TurnPhase::Defeat);
+}
+
+#[test]
+fn clearing_the_last_wave_is_victory() {
+    let mut harness = Harness::new();
+    let ai_units = harness.ai_units();
+    harness.despawn(ai_units);
+    harness.run_until(TurnPhase::Victory);
+}
+
+#[test]
+fn obstacles_block_ranged_attacks() {
+    // pirate 3 (range 4) stands on (5, 4), the obstacle on (7, 4) hides (8, 4)
+    let mut harness = Harness::new();
+    let pirate = harness.unit_at((5, 4)).unwrap();
+    let mut ai_units = harness.ai_units();
+    let hidden = ai_units.pop().unwrap();
+    let visible = ai_units.pop().unwrap();
+    harness.place(hidden, (8, 4));
+    harness.place(visible, (8, 5));
+    // pirate 2 keeps an eye on (8, 4), so it is seen but not in sight of pirate 3
+    let lookout = harness.unit_at((3, 4)).unwrap();
+    harness.place(lookout, (8, 2));
+    let health = harness.health(hidden);
+
+    harness.click_tile((5, 4));
+    harness.press_key(KeyCode::Space);
+    assert_eq!(harness.phase(), TurnPhase::SelectTarget);
+    let color = |harness: &mut Harness, tile: (i32, i32)| {
+        let mut query = harness.app.world.query::<(&Tile, &GridPosition, &Sprite)>();
+        query
+            .iter(&harness.app.world)
+            .find(|(_tile, grid, _sprite)| (grid.x, grid.y) == tile)
+            .map(|(_tile, _grid, sprite)| sprite.color)
+            .unwrap()
+    };
+    // seen but out of sight tiles are greyed out, targets in sight turn red
+    assert_eq!(color(&mut harness, (8, 4)), Color::rgb(0.5, 0.5, 0.5));
+    assert_eq!(color(&mut harness, (8, 5)), Color::rgb(1.0, 0.0, 0.0));
+
+    harness.hover_tile(Some((8, 4)));
+    assert_eq!(harness.forecast(), None);
+    harness.click_tile((8, 4));
+    harness.click_tile((8, 4));
+    assert_eq!(harness.phase(), TurnPhase::SelectTarget);
+    assert_eq!(harness.health(hidden), health);
+
+    harness.click_tile((8, 5));
+    harness.click_tile((8, 5));
+    harness.run_until(TurnPhase::SelectUnit);
+    assert!(harness.unit(pirate).is_done());
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use bevy::prelude::*;
+use common::{Harness, PIRATE_1};
+use tbt::{
+    level::{Level, LevelHandles},
+    rng::GameRng,
+    sim::Odds,
+    states::TurnPhase,
+    units::{Attack, Health},
+};
+
+fn undo(harness: &mut Harness) {
+    harness.press_key(KeyCode::U);
+    harness.run_until(TurnPhase::SelectUnit);
+}
+
+/// Opens and closes a unit so the tweaked board is snapshotted as the turn's state.
+fn checkpoint(harness: &mut Harness) {
+    harness.click_tile(PIRATE_1);
+    harness.press_key(KeyCode::Escape);
+}
+
+fn set_undo_limit(harness: &mut Harness, limit: usize) {
+    let handle = harness.app.world.resource::<LevelHandles>().level.clone();
+    let mut levels = harness.app.world.resource_mut::<Assets<Level>>();
+    levels.get_mut(&handle).unwrap().undo_limit = Some(limit);
+}
+
+#[test]
+fn undo_takes_back_an_attack() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    let target = harness.ai_units()[0];
+    harness.place(target, (4, 5));
+    checkpoint(&mut harness);
+    let health = harness.health(target);
+    harness.attack(PIRATE_1, (4, 5));
+    assert_eq!(harness.health(target), health - 7);
+
+    undo(&mut harness);
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    assert!(!harness.unit(pirate).is_done());
+    let target = harness.unit_at((4, 5)).unwrap();
+    assert_eq!(harness.health(target), health);
+}
+
+#[test]
+fn undo_does_not_reroll_a_missed_attack() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    harness
+        .app
+        .world
+        .get_mut::<Attack>(pirate)
+        .unwrap()
+        .accuracy = 50;
+    // a seed whose first roll misses the pirate's coin flip
+    let odds = Odds::new(50, 0, 0, 7, 7);
+    let seed = (0..)
+        .find(|seed| !odds.roll(&mut GameRng::new(*seed)).hit)
+        .unwrap();
+    harness.app.world.insert_resource(GameRng::new(seed));
+    let target = harness.ai_units()[0];
+    harness.place(target, (4, 5));
+    checkpoint(&mut harness);
+    let health = harness.health(target);
+
+    for _ in 0..5 {
+        harness.attack(PIRATE_1, (4, 5));
+        let target = harness.unit_at((4, 5)).unwrap();
+        assert_eq!(harness.health(target), health);
+        undo(&mut harness);
+    }
+}
+
+#[test]
+fn undo_takes_back_an_unfinished_move() {
+    let mut harness = Harness::new();
+    harness.click_tile(PIRATE_1);
+    harness.click_tile((4, 5));
+    harness.run_until(TurnPhase::SelectTarget);
+
+    undo(&mut harness);
+    let pirate = harness.unit_at(PIRATE_1).unwrap();
+    assert!(!harness.unit(pirate).has_moved);
+    assert_eq!(harness.unit_at((4, 5)), None);
+}
+
+#[test]
+fn undo_restores_killed_units() {
+    let mut harness = Harness::new();
+    harness.sure_hits();
+    let target = harness.ai_units()[0];
+    harness.place(target, (4, 5));
+    harness.app.world.get_mut::<Health>(target).unwrap().value = 5;
+    checkpoint(&mut harness);
+    harness.attack(PIRATE_1, (4, 5));
+    assert_eq!(harness.ai_units().len(), 3);
+
+    undo(&mut harness);
+    assert_eq!(harness.ai_units().len(), 4);
+    let target = harness.unit_at((4, 5)).unwrap();
+    assert_eq!(harness.health(target), 5);
+}
+
+#[test]
+fn undos_stop_at_the_level_limit() {
+    let mut harness = Harness::new();
+    set_undo_limit(&mut harness, 1);
+    for tile in [PIRATE_1, (3, 4)] {
+        harness.click_tile(tile);
+        harness.press_key(KeyCode::Space);
+        harness.press_key(KeyCode::Space);
+    }
+
+    undo(&mut harness);
+    let first = harness.unit_at(PIRATE_1).unwrap();
+    let second = harness.unit_at((3, 4)).unwrap();
+    assert!(harness.unit(first).is_done());
+    assert!(!harness.unit(second).is_done());
+
+    harness.press_key(KeyCode::U);
+    assert_eq!(harness.phase(), TurnPhase::SelectUnit);
+    assert!(harness.unit(first).is_done());
+}
+
+#[test]
+fn undo_history_ends_with_the_player_turn() {
+    let mut harness = Harness::new();
+    harness.press_key(KeyCode::Space);
+    harness.run_while(TurnPhase::SelectUnit);
+    harness.run_until(TurnPhase::SelectUnit);
+
+    harness.press_key(KeyCode::U);
+    assert_eq!(harness.phase(), TurnPhase::SelectUnit);
+}